@@ -26,6 +26,7 @@ cp "$bandcamp_json" .
 
 mkdir hsmusic-data
 cp -r "$hsmusic_data/album" hsmusic-data/
+cp "$hsmusic_data"/artists.* hsmusic-data/
 
 mkdir hsmusic-media
 cp -r "$hsmusic_media/album-art" hsmusic-media/
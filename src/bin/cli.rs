@@ -1,12 +1,28 @@
-use anyhow::Result;
-use clap::Parser;
-use hsmusicifier::{add_art, ArtType, ArtTypes, Edits};
-use std::path::PathBuf;
+use anyhow::{anyhow, ensure, Context, Result};
+use clap::{Parser, Subcommand};
+use hsmusicifier::{
+    add_art,
+    groups::GroupMapping,
+    hsmusic::LoadMode,
+    locate::{candidates, find_hsmusic_from_ids},
+    manual::{hash_key, path_key, ManualMatches},
+    matching::{parse_confidence, Query},
+    merge::{parse_field_policy, Merge, MergePolicy},
+    profile::Profile,
+    report::{write_report, ReportFormat},
+    template::PathTemplate,
+    ArtType, ArtTypes, ArtistStyle, Commentary, CommentaryField, CommentarySource, Data, Edits,
+    LyricsMode,
+};
+use lofty::ItemKey;
+use std::io::{stdin, stdout, Write};
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[clap(
     name = "hsmusicifier",
-    about = "A tool to add track art to Homestuck music."
+    about = "A tool to add track art to Homestuck music.",
+    subcommand_negates_reqs = true
 )]
 struct Opt {
     /// Location of dumped bandcamp json
@@ -18,20 +34,37 @@ struct Opt {
     pub hsmusic_data: PathBuf,
 
     /// Location of hsmusic-media
-    #[clap(short = 'm', long, parse(from_os_str))]
-    pub hsmusic_media: PathBuf,
+    #[clap(short = 'm', long, parse(from_os_str), required = true)]
+    pub hsmusic_media: Option<PathBuf>,
+
+    /// Fail on broken hsmusic-data (strict) or skip it with a warning (lenient)
+    #[clap(long, default_value = "strict")]
+    pub load_mode: LoadMode,
+
+    /// Location of a cache for parsed hsmusic-data, created if missing
+    #[clap(long, parse(from_os_str))]
+    pub cache: Option<PathBuf>,
 
     /// Verbosity
     #[clap(short, long)]
     pub verbose: bool,
 
+    /// Match files and report what would change, without writing anything
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Print a report of every file as a table, JSON or CSV to stdout, with progress and warnings
+    /// on stderr. Dry runs default to a table
+    #[clap(long)]
+    pub report: Option<ReportFormat>,
+
     /// Input directory
-    #[clap(parse(from_os_str))]
-    pub in_dir: PathBuf,
+    #[clap(parse(from_os_str), required = true)]
+    pub in_dir: Option<PathBuf>,
 
     /// Output directory
-    #[clap(parse(from_os_str))]
-    pub out_dir: PathBuf,
+    #[clap(parse(from_os_str), required = true)]
+    pub out_dir: Option<PathBuf>,
 
     /// Don't add art
     #[clap(long)]
@@ -49,9 +82,237 @@ struct Opt {
     #[clap(long)]
     pub no_artists: bool,
 
+    /// Write artists joined into one value, as multiple values, or as "first feat. rest"
+    #[clap(long, default_value = "joined", conflicts_with = "no-artists")]
+    pub artist_style: ArtistStyle,
+
+    /// Separator between artists when joined
+    #[clap(long, default_value = ", ", conflicts_with = "no-artists")]
+    pub artist_separator: String,
+
+    /// Add role-aware credits for performers and contributors
+    #[clap(long)]
+    pub credits: bool,
+
     /// Add album
     #[clap(long)]
     pub album: bool,
+
+    /// Number discs by the album's group sections
+    #[clap(long, requires = "album")]
+    pub disc_numbers: bool,
+
+    /// Embed lyrics, or write them to an .lrc or .txt file next to each track
+    #[clap(long)]
+    pub lyrics: Option<LyricsMode>,
+
+    /// Add track, album or both commentary
+    #[clap(long)]
+    pub commentary: Option<CommentarySource>,
+
+    /// Store commentary in the comment field or a custom HSMUSIC_COMMENTARY field
+    #[clap(long, default_value = "comment")]
+    pub commentary_field: CommentaryField,
+
+    /// Cut off commentary after this many characters
+    #[clap(long, requires = "commentary")]
+    pub commentary_max_len: Option<usize>,
+
+    /// YAML or JSON table mapping hsmusic groups and art tags to genre and grouping values
+    #[clap(long, parse(from_os_str))]
+    pub group_mapping: Option<PathBuf>,
+
+    /// Add the group a track is in within its album as the disc subtitle
+    #[clap(long)]
+    pub group_subtitle: bool,
+
+    /// Add release, original release and cover art dates
+    #[clap(long)]
+    pub dates: bool,
+
+    /// Record the matched hsmusic album and track, and the Bandcamp URL, for faster re-runs
+    #[clap(long)]
+    pub ids: bool,
+
+    /// Add links to the track's releases and artist
+    #[clap(long)]
+    pub urls: bool,
+
+    /// Only add links on this host or its subdomains, e.g. bandcamp.com
+    #[clap(long, requires = "urls")]
+    pub url_host: Vec<String>,
+
+    /// Add the track's color
+    #[clap(long)]
+    pub color: bool,
+
+    /// Add the tracks each track references, and the tracks referencing it
+    #[clap(long)]
+    pub references: bool,
+
+    /// Warn when a file's length differs from the hsmusic track's by more than this many seconds
+    #[clap(long)]
+    pub check_duration: Option<u64>,
+
+    /// Which fields to write hsmusic data to: foobar2000, musicbee, navidrome, itunes, or a TOML
+    /// or JSON profile file
+    #[clap(long)]
+    pub profile: Option<String>,
+
+    /// How to treat fields files already have: overwrite, fill-empty, append or keep
+    #[clap(long, default_value = "overwrite")]
+    pub merge: MergePolicy,
+
+    /// How to treat one field, as FIELD=POLICY, e.g. TrackArtist=keep or Picture=fill-empty
+    #[clap(long, parse(try_from_str = parse_field_policy))]
+    pub merge_field: Vec<(String, MergePolicy)>,
+
+    /// Copy values that get changed to ORIGINAL_<FIELD>
+    #[clap(long)]
+    pub backup_original: bool,
+
+    /// Template for matching files without tags by their path, e.g. "{album}/{track:02} {title}",
+    /// tried in order
+    #[clap(long, default_value = "{artist} - {title}")]
+    pub path_template: Vec<PathTemplate>,
+
+    /// How confident, from 0 to 1, a fuzzy match by title, track number and length has to be.
+    /// Tracks with no confident match, or two matches too close to call, are reported as
+    /// ambiguous
+    #[clap(long, default_value = "0.65", parse(try_from_str = parse_confidence))]
+    pub min_confidence: f64,
+
+    /// YAML file of files matched by hand, matches.yaml next to the bandcamp json by default
+    #[clap(long, parse(from_os_str))]
+    pub manual_matches: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Pick the hsmusic track for a file the matcher got wrong or couldn't match, and save it as a
+    /// manual match
+    AddMatch {
+        /// Input directory the file is in
+        #[clap(parse(from_os_str))]
+        in_dir: PathBuf,
+
+        /// The file to match
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+
+        /// Match the file by its contents rather than its path
+        #[clap(long)]
+        by_hash: bool,
+    },
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_match(
+    bandcamp_json: &Path,
+    hsmusic_data: &Path,
+    load_mode: LoadMode,
+    cache: Option<&Path>,
+    manual_matches: &Path,
+    path_templates: &[PathTemplate],
+    in_dir: &Path,
+    file: &Path,
+    by_hash: bool,
+) -> Result<()> {
+    let rel_path = file
+        .strip_prefix(in_dir)
+        .with_context(|| format!("{} isn't in {}", file.display(), in_dir.display()))?;
+
+    let data = Data::read(bandcamp_json, hsmusic_data, load_mode, cache)?;
+    for warning in &data.warnings {
+        eprintln!("skipping broken hsmusic-data: {}", warning);
+    }
+    let artists = data.artists()?;
+    let albums = data.albums(&artists);
+    let catalog = data.catalog(&albums);
+
+    let metadata = lofty::read_from_path(file, true)?;
+    let tag = |key| metadata.first_tag().and_then(|x| x.get_string(&key));
+    let info = path_templates
+        .iter()
+        .find_map(|x| x.parse(rel_path))
+        .unwrap_or_default();
+
+    let album = tag(ItemKey::AlbumTitle).map(str::to_string).or(info.album);
+    let title = tag(ItemKey::TrackTitle)
+        .map(str::to_string)
+        .or(info.title)
+        .or_else(|| Some(file.file_stem()?.to_str()?.to_string()))
+        .unwrap_or_default();
+    let query = Query {
+        title: &title,
+        track_num: tag(ItemKey::TrackNumber)
+            .and_then(|x| x.parse().ok())
+            .or(info.track_num),
+        duration: Some(metadata.properties().duration().as_secs()).filter(|&x| x > 0),
+    };
+
+    let candidates = candidates(album.as_deref(), &query, &catalog);
+    let candidates = &candidates[..10.min(candidates.len())];
+
+    println!("{}", file.display());
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!(
+            "{:>3}. {} - {} ({}/{}, {:.0}%)",
+            i + 1,
+            candidate.album.name,
+            candidate.track.name,
+            candidate.album.directory,
+            candidate.track.directory,
+            candidate.confidence * 100.0
+        );
+    }
+    print!("Pick a number, or enter album/track directories: ");
+    stdout().flush()?;
+
+    let mut line = String::new();
+    stdin().read_line(&mut line)?;
+    let line = line.trim();
+
+    let (album, track) = match line.parse::<usize>() {
+        Ok(choice) => {
+            ensure!(
+                (1..=candidates.len()).contains(&choice),
+                "Bad choice {}!",
+                choice
+            );
+            let candidate = &candidates[choice - 1];
+            (candidate.album, candidate.track)
+        }
+        Err(_) => {
+            let (album, track) = line
+                .split_once('/')
+                .with_context(|| format!("expected a number or album/track, got {}", line))?;
+            find_hsmusic_from_ids(album, track, &catalog)
+                .with_context(|| format!("couldn't find track {}", line))?
+        }
+    };
+
+    let key = if by_hash {
+        hash_key(file)?
+    } else {
+        path_key(rel_path).context("file name isn't valid UTF-8")?
+    };
+
+    let mut matches = ManualMatches::read(manual_matches)?;
+    matches.insert(key, &album.directory, &track.directory);
+    matches.write(manual_matches)?;
+
+    println!(
+        "Matched {} to {:?} - {:?}",
+        rel_path.display(),
+        album.name,
+        track.name
+    );
+
+    Ok(())
 }
 
 fn main() -> Result<()> {
@@ -61,16 +322,67 @@ fn main() -> Result<()> {
         bandcamp_json,
         hsmusic_data,
         hsmusic_media,
+        load_mode,
+        cache,
         verbose,
+        dry_run,
+        report,
         in_dir,
         out_dir,
         no_art,
         first_art,
         rest_art,
         no_artists,
+        artist_style,
+        artist_separator,
+        credits,
         album,
+        disc_numbers,
+        lyrics,
+        commentary,
+        commentary_field,
+        commentary_max_len,
+        group_mapping,
+        group_subtitle,
+        dates,
+        ids,
+        urls,
+        url_host,
+        color,
+        references,
+        check_duration,
+        profile,
+        merge,
+        merge_field,
+        backup_original,
+        path_template,
+        min_confidence,
+        manual_matches,
+        command,
     } = opt;
 
+    let manual_matches =
+        manual_matches.unwrap_or_else(|| ManualMatches::path_beside(&bandcamp_json));
+
+    if let Some(Command::AddMatch {
+        in_dir,
+        file,
+        by_hash,
+    }) = command
+    {
+        return add_match(
+            &bandcamp_json,
+            &hsmusic_data,
+            load_mode,
+            cache.as_deref(),
+            &manual_matches,
+            &path_template,
+            &in_dir,
+            &file,
+            by_hash,
+        );
+    }
+
     let edits = Edits {
         add_art: if no_art {
             None
@@ -80,20 +392,89 @@ fn main() -> Result<()> {
                 rest: rest_art,
             })
         },
-        add_artists: !no_artists,
+        add_artists: if no_artists {
+            None
+        } else {
+            Some(match artist_style {
+                ArtistStyle::Joined(_) => ArtistStyle::Joined(artist_separator),
+                other => other,
+            })
+        },
+        add_credits: credits,
         add_album: album,
+        add_disc_numbers: disc_numbers,
+        add_lyrics: lyrics,
+        add_commentary: commentary.map(|source| Commentary {
+            source,
+            field: commentary_field,
+            max_len: commentary_max_len,
+        }),
+        add_genres: group_mapping.map(GroupMapping::read).transpose()?,
+        add_group_subtitle: group_subtitle,
+        add_dates: dates,
+        add_ids: ids,
+        add_urls: if urls { Some(url_host) } else { None },
+        add_color: color,
+        add_references: references,
+        check_duration,
+        profile: match profile {
+            Some(profile) => Profile::load(&profile)?,
+            None => Profile::default(),
+        },
+        merge: Merge::new(merge, merge_field, backup_original),
+        path_templates: path_template,
+        min_confidence,
+        manual_matches: ManualMatches::read(&manual_matches)?,
     };
 
-    add_art(
+    let run_report = add_art(
         bandcamp_json,
         hsmusic_data,
-        hsmusic_media,
+        hsmusic_media.expect("hsmusic-media is required"),
+        load_mode,
+        cache,
         edits,
         verbose,
-        in_dir,
-        out_dir,
+        dry_run,
+        in_dir.expect("in-dir is required"),
+        out_dir.expect("out-dir is required"),
         drop,
     )?;
 
-    Ok(())
+    let report = if dry_run {
+        Some(report.unwrap_or(ReportFormat::Table))
+    } else {
+        report
+    };
+    if let Some(format) = report {
+        write_report(&run_report.files, format, stdout().lock())?;
+    }
+
+    for warning in &run_report.warnings {
+        eprintln!("skipping broken hsmusic-data: {}", warning);
+    }
+    for file in &run_report.files {
+        for warning in &file.warnings {
+            eprintln!("warning: {}: {}", file.path.display(), warning);
+        }
+    }
+
+    let mut problems = 0;
+    for file in run_report.problems() {
+        problems += 1;
+        eprintln!(
+            "{} ({}): {}",
+            file.path.display(),
+            file.outcome,
+            file.errors.join(": ")
+        );
+    }
+
+    let summary = run_report.summary();
+    if problems > 0 && !dry_run {
+        Err(anyhow!("{}", summary))
+    } else {
+        eprintln!("{}", summary);
+        Ok(())
+    }
 }
@@ -0,0 +1,134 @@
+//! an index of the hsmusic and Bandcamp catalogs, built once and shared by every lookup
+use crate::matching::normalize;
+use crate::overrides::Overrides;
+use crate::{bandcamp, hsmusic};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A track, with its album and its name normalized for matching.
+#[derive(Clone, Debug)]
+pub struct Entry<'c> {
+    pub album: &'c hsmusic::Album<'c>,
+    pub track: &'c hsmusic::Track<'c>,
+    pub name: String,
+}
+
+/// Every hsmusic track, indexed by normalized name, by directory and by URL, and every Bandcamp
+/// album by normalized name, along with the overrides to apply to lookups. When several tracks or
+/// albums share a key, the first one wins, just like a scan would.
+#[derive(Debug)]
+pub struct Catalog<'c> {
+    entries: Vec<Entry<'c>>,
+    albums_by_name: HashMap<String, &'c hsmusic::Album<'c>>,
+    album_entries: HashMap<&'c str, Range<usize>>,
+    by_name: HashMap<String, Vec<usize>>,
+    by_directory: HashMap<(&'c str, &'c str), usize>,
+    by_url: HashMap<&'c str, usize>,
+    bandcamp_by_name: HashMap<String, &'c bandcamp::Album>,
+    overrides: Overrides,
+}
+
+impl<'c> Catalog<'c> {
+    pub fn new(
+        bandcamp_albums: &'c [bandcamp::Album],
+        hsmusic_albums: &'c [hsmusic::Album<'c>],
+        overrides: Overrides,
+    ) -> Self {
+        let mut catalog = Self {
+            entries: vec![],
+            albums_by_name: HashMap::new(),
+            album_entries: HashMap::new(),
+            by_name: HashMap::new(),
+            by_directory: HashMap::new(),
+            by_url: HashMap::new(),
+            bandcamp_by_name: HashMap::new(),
+            overrides,
+        };
+
+        for album in hsmusic_albums {
+            catalog
+                .albums_by_name
+                .entry(normalize(album.name))
+                .or_insert(album);
+
+            let excluded = catalog.overrides.is_excluded(album.name);
+            let start = catalog.entries.len();
+            for track in &album.tracks {
+                let index = catalog.entries.len();
+                let name = normalize(&track.name);
+
+                catalog.by_name.entry(name.clone()).or_default().push(index);
+                catalog
+                    .by_directory
+                    .entry((&*album.directory, &*track.directory))
+                    .or_insert(index);
+                if !excluded {
+                    for &url in &track.urls {
+                        catalog.by_url.entry(url).or_insert(index);
+                    }
+                }
+
+                catalog.entries.push(Entry { album, track, name });
+            }
+            catalog
+                .album_entries
+                .entry(&*album.directory)
+                .or_insert(start..catalog.entries.len());
+        }
+
+        for album in bandcamp_albums {
+            catalog
+                .bandcamp_by_name
+                .entry(normalize(&album.name))
+                .or_insert(album);
+        }
+
+        catalog
+    }
+
+    pub fn overrides(&self) -> &Overrides {
+        &self.overrides
+    }
+
+    /// Every track, in catalog order.
+    pub fn tracks(&self) -> &[Entry<'c>] {
+        &self.entries
+    }
+
+    /// The tracks in `album`, in order.
+    pub fn album_tracks(&self, album: &hsmusic::Album) -> &[Entry<'c>] {
+        self.album_entries
+            .get(&*album.directory)
+            .map_or(&[], |x| &self.entries[x.clone()])
+    }
+
+    /// The album with this name, once normalized.
+    pub fn album(&self, name: &str) -> Option<&'c hsmusic::Album<'c>> {
+        self.albums_by_name.get(&normalize(name)).copied()
+    }
+
+    /// The tracks with this name, once normalized.
+    pub fn tracks_named(&self, name: &str) -> impl Iterator<Item = &Entry<'c>> {
+        self.by_name
+            .get(&normalize(name))
+            .into_iter()
+            .flatten()
+            .map(move |&x| &self.entries[x])
+    }
+
+    pub fn from_ids(&self, album_directory: &str, track_directory: &str) -> Option<&Entry<'c>> {
+        self.by_directory
+            .get(&(album_directory, track_directory))
+            .map(|&x| &self.entries[x])
+    }
+
+    /// The track released at `url`, leaving out collections that only link to it.
+    pub fn from_url(&self, url: &str) -> Option<&Entry<'c>> {
+        self.by_url.get(url).map(|&x| &self.entries[x])
+    }
+
+    /// The Bandcamp album with this name, once normalized.
+    pub fn bandcamp_album(&self, name: &str) -> Option<&'c bandcamp::Album> {
+        self.bandcamp_by_name.get(&normalize(name)).copied()
+    }
+}
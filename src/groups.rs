@@ -0,0 +1,86 @@
+//! mapping hsmusic groups and art tags to genre and grouping tag values
+use crate::hsmusic::{get_kebab_case, Album, Track};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// A user-supplied table of which groups and art tags become which tag values, e.g.
+///
+/// ```yaml
+/// genre:
+///   fandom: Fan Music
+///   official: Soundtrack
+/// grouping:
+///   beyond-canon: Beyond Canon
+/// ```
+///
+/// Keys are group or art tag names or directories (`group:fandom`, `Fandom` and `fandom` are all
+/// the same). Anything that isn't in the table isn't written.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct GroupMapping {
+    #[serde(default)]
+    pub genre: HashMap<String, String>,
+    #[serde(default)]
+    pub grouping: HashMap<String, String>,
+}
+
+fn normalize(name: &str) -> String {
+    let name = name
+        .strip_prefix("group:")
+        .or_else(|| name.strip_prefix("tag:"))
+        .unwrap_or(name);
+    get_kebab_case(name)
+}
+
+fn lookup(table: &HashMap<String, String>, album: &Album, track: &Track) -> Vec<String> {
+    let mut values: Vec<String> = vec![];
+
+    for name in album
+        .groups
+        .iter()
+        .chain(&album.art_tags)
+        .chain(&track.art_tags)
+    {
+        let name = normalize(name);
+        if let Some(value) = table.get(&name) {
+            if !values.contains(value) {
+                values.push(value.clone());
+            }
+        }
+    }
+
+    values
+}
+
+impl GroupMapping {
+    /// Reads a mapping table from a YAML or JSON file.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let string = read_to_string(path)
+            .with_context(|| format!("failed to read group mapping {}", path.display()))?;
+        let mapping: Self = serde_yaml::from_str(&string)
+            .with_context(|| format!("failed to parse group mapping {}", path.display()))?;
+
+        Ok(Self {
+            genre: Self::normalize_keys(mapping.genre),
+            grouping: Self::normalize_keys(mapping.grouping),
+        })
+    }
+
+    fn normalize_keys(table: HashMap<String, String>) -> HashMap<String, String> {
+        table
+            .into_iter()
+            .map(|(name, value)| (normalize(&name), value))
+            .collect()
+    }
+
+    pub fn genres(&self, album: &Album, track: &Track) -> Vec<String> {
+        lookup(&self.genre, album, track)
+    }
+
+    pub fn groupings(&self, album: &Album, track: &Track) -> Vec<String> {
+        lookup(&self.grouping, album, track)
+    }
+}
@@ -1,13 +1,25 @@
 //! ported basically verbatim from original JS
+// ParseError carries enough location info to trip this, and errors are rare
+#![allow(clippy::result_large_err)]
 use super::ArtType;
-use anyhow::{ensure, Context, Result};
+use anyhow::{anyhow, ensure, Context, Error, Result};
 use chrono::naive::NaiveDate;
 use either::{Left, Right};
 use htmlescape::decode_html;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::fmt;
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub mod artists;
+pub mod cache;
+pub mod owned;
+pub mod references;
+pub mod yaml;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Contributor<'a> {
@@ -85,6 +97,163 @@ pub struct Album<'a> {
     pub tracks: Vec<Track<'a>>,
 }
 
+/// An error in an hsmusic-data file, with as much location information as is known.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseError {
+    pub path: Option<PathBuf>,
+    /// Index of the section within the file, where the album (or first artist) is section 0.
+    pub section: Option<usize>,
+    /// 1-based line number within the file.
+    pub line: Option<usize>,
+    pub field: Option<String>,
+    pub value: Option<String>,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(message: impl fmt::Display) -> Self {
+        Self {
+            path: None,
+            section: None,
+            line: None,
+            field: None,
+            value: None,
+            message: message.to_string(),
+        }
+    }
+
+    fn in_section<'a>(section: impl Section<'a>, message: impl fmt::Display) -> Self {
+        Self {
+            section: Some(section.index()),
+            line: Some(section.line()),
+            ..Self::new(message)
+        }
+    }
+
+    fn missing<'a>(section: impl Section<'a>, field: &str) -> Self {
+        Self {
+            field: Some(field.to_string()),
+            ..Self::in_section(section, format!("missing {}", field))
+        }
+    }
+
+    fn bad_field<'a>(
+        section: impl Section<'a>,
+        field: &str,
+        value: &str,
+        message: impl fmt::Display,
+    ) -> Self {
+        Self {
+            line: section.field_line(field).or_else(|| Some(section.line())),
+            field: Some(field.to_string()),
+            value: Some(value.to_string()),
+            ..Self::in_section(section, message)
+        }
+    }
+
+    fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}", path.display())?,
+            None => write!(f, "<unknown>")?,
+        }
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(section) = self.section {
+            write!(f, ": section {}", section)?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, ", field {:?}", field)?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, " = {:?}", value)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Every error encountered while loading a set of hsmusic-data files.
+#[derive(Debug, Clone)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} errors in hsmusic-data:", self.0.len())?;
+        for error in &self.0 {
+            writeln!(f, "* {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+/// A single section of an hsmusic-data file, in either the legacy text format or YAML.
+trait Section<'a>: Copy {
+    fn index(self) -> usize;
+    fn line(self) -> usize;
+    fn field_line(self, name: &str) -> Option<usize>;
+    fn is_empty(self) -> bool;
+    fn basic_field(self, name: &str) -> Option<&'a str>;
+    fn list_field(self, name: &str) -> Option<Vec<&'a str>>;
+    fn multiline_field(self, name: &str) -> Option<String>;
+}
+
+#[derive(Debug, Copy, Clone)]
+struct TextSection<'a> {
+    text: &'a str,
+    index: usize,
+    line: usize,
+}
+
+impl<'a> Section<'a> for TextSection<'a> {
+    fn index(self) -> usize {
+        self.index
+    }
+
+    fn line(self) -> usize {
+        self.line
+    }
+
+    fn field_line(self, name: &str) -> Option<usize> {
+        self.text
+            .lines()
+            .position(|line| line.starts_with(&format!("{}:", name)))
+            .map(|i| self.line + i)
+    }
+
+    fn is_empty(self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    fn basic_field(self, name: &str) -> Option<&'a str> {
+        get_basic_field(self.text, name)
+    }
+
+    fn list_field(self, name: &str) -> Option<Vec<&'a str>> {
+        get_list_field(self.text, name)
+    }
+
+    fn multiline_field(self, name: &str) -> Option<String> {
+        get_multiline_field(self.text, name)
+    }
+}
+
+fn required_field<'a>(section: impl Section<'a>, name: &str) -> Result<&'a str, ParseError> {
+    section
+        .basic_field(name)
+        .ok_or_else(|| ParseError::missing(section, name))
+}
+
 fn get_basic_field<'a, 'b>(s: &'a str, name: &'b str) -> Option<&'a str> {
     if let Some(line) = s
         .lines()
@@ -96,8 +265,8 @@ fn get_basic_field<'a, 'b>(s: &'a str, name: &'b str) -> Option<&'a str> {
     }
 }
 
-fn get_date_field(s: &str, name: &str) -> Result<Option<NaiveDate>> {
-    get_basic_field(s, name)
+fn get_date_field<'a>(s: impl Section<'a>, name: &str) -> Result<Option<NaiveDate>, ParseError> {
+    s.basic_field(name)
         .map(|x| {
             NaiveDate::parse_from_str(
                 &x[..x.match_indices(' ').nth(2).unwrap_or((x.len(), "")).0]
@@ -108,9 +277,9 @@ fn get_date_field(s: &str, name: &str) -> Result<Option<NaiveDate>> {
                     "%B %-d %Y"
                 },
             )
+            .map_err(|err| ParseError::bad_field(s, name, x, err))
         })
         .transpose()
-        .map_err(From::from)
 }
 
 fn get_list_field<'a, 'b>(s: &'a str, name: &'b str) -> Option<Vec<&'a str>> {
@@ -143,16 +312,16 @@ fn get_list_field<'a, 'b>(s: &'a str, name: &'b str) -> Option<Vec<&'a str>> {
     }
 }
 
-fn get_contribution_field<'a, 'b>(s: &'a str, name: &'b str) -> Option<Vec<Contributor<'a>>> {
-    let contributors = get_list_field(s, name)?;
+fn get_contribution_field<'a>(s: impl Section<'a>, name: &str) -> Option<Vec<Contributor<'a>>> {
+    let contributors = s.list_field(name)?;
 
     static REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("^(.*?)( \\((.*)\\))?$").unwrap());
     let mapped: Vec<Contributor> = contributors
         .into_iter()
         .map(|contrib| {
             if let Some(captures) = REGEX.captures(contrib) {
-                let who = captures.get(0)?.as_str();
-                let what = captures.get(2).map(|x| x.as_str());
+                let who = captures.get(1)?.as_str();
+                let what = captures.get(3).map(|x| x.as_str());
 
                 Some(Contributor { who, what })
             } else {
@@ -202,7 +371,7 @@ fn get_multiline_field(s: &str, name: &str) -> Option<String> {
     }
 }
 
-fn get_kebab_case(name: &str) -> String {
+pub(crate) fn get_kebab_case(name: &str) -> String {
     let mut s: String = name
         .chars()
         .map(|x| if x == ' ' { '-' } else { x })
@@ -232,17 +401,178 @@ fn get_kebab_case(name: &str) -> String {
 
 static SPLIT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("(?m)^-{8,}\n").unwrap());
 
-pub fn parse_artist(string: &str) -> Result<Artist> {
+fn text_sections(string: &str) -> Vec<TextSection<'_>> {
+    let mut sections = vec![];
+    let mut start = 0;
+    let mut line = 1;
+
+    for separator in SPLIT_REGEX.find_iter(string) {
+        sections.push(TextSection {
+            text: &string[start..separator.start()],
+            index: sections.len(),
+            line,
+        });
+        line += string[start..separator.end()].matches('\n').count();
+        start = separator.end();
+    }
+
+    sections.push(TextSection {
+        text: &string[start..],
+        index: sections.len(),
+        line,
+    });
+
+    sections
+}
+
+fn parse_artist_section<'a>(section: impl Section<'a>) -> Result<Artist<'a>, ParseError> {
     Ok(Artist {
-        name: get_basic_field(string, "Artist").context("no Artist")?,
-        urls: get_list_field(string, "URLs").unwrap_or_default(),
-        alias: get_basic_field(string, "Alias"),
-        note: get_multiline_field(string, "Note"),
+        name: required_field(section, "Artist")?,
+        urls: section.list_field("URLs").unwrap_or_default(),
+        alias: section.basic_field("Alias"),
+        note: section.multiline_field("Note"),
     })
 }
 
+pub fn parse_artist(string: &str) -> Result<Artist> {
+    Ok(parse_artist_section(TextSection {
+        text: string,
+        index: 0,
+        line: 1,
+    })?)
+}
+
 pub fn parse_artists(string: &str) -> Result<Vec<Artist>> {
-    SPLIT_REGEX.split(string).map(parse_artist).collect()
+    Ok(text_sections(string)
+        .into_iter()
+        .map(parse_artist_section)
+        .collect::<Result<_, _>>()?)
+}
+
+#[derive(Debug)]
+enum Contents {
+    Text(String),
+    Yaml(Vec<yaml::Document>),
+}
+
+/// The contents of an hsmusic-data file, in either the legacy text format or YAML.
+#[derive(Debug)]
+pub struct DataFile {
+    pub path: PathBuf,
+    contents: Contents,
+}
+
+impl DataFile {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let string =
+            read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+        Ok(Self::from_string(path, string)?)
+    }
+
+    /// Wraps the already-read contents of the file at `path`.
+    pub fn from_string(path: impl AsRef<Path>, string: String) -> Result<Self, ParseError> {
+        let path = path.as_ref();
+        let is_yaml = match path.extension().and_then(|x| x.to_str()) {
+            Some("yaml") | Some("yml") => true,
+            Some("txt") => false,
+            _ => yaml::is_yaml(&string),
+        };
+
+        let contents = if is_yaml {
+            Contents::Yaml(yaml::parse_documents(&string).map_err(|err| err.with_path(path))?)
+        } else {
+            Contents::Text(string)
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            contents,
+        })
+    }
+
+    /// Parses an album, skipping any broken tracks and recording them in `warnings`.
+    pub fn parse_album_lenient(
+        &self,
+        warnings: &mut Vec<ParseError>,
+    ) -> Result<Album<'_>, ParseError> {
+        let mut track_warnings = vec![];
+
+        let album = match &self.contents {
+            Contents::Text(string) => {
+                let sections = text_sections(string);
+                parse_album_sections(
+                    sections[0],
+                    sections[1..].iter().copied(),
+                    Some(&mut track_warnings),
+                )
+            }
+            Contents::Yaml(documents) => match documents.split_first() {
+                Some((album_section, sections)) => {
+                    parse_album_sections(album_section, sections, Some(&mut track_warnings))
+                }
+                None => Err(ParseError::new("no album section")),
+            },
+        }
+        .map_err(|err| err.with_path(&self.path))?;
+
+        warnings.extend(
+            track_warnings
+                .into_iter()
+                .map(|err| err.with_path(&self.path)),
+        );
+
+        Ok(album)
+    }
+
+    pub fn parse_artists(&self) -> Result<Vec<Artist<'_>>, ParseError> {
+        match &self.contents {
+            Contents::Text(string) => text_sections(string)
+                .into_iter()
+                .map(parse_artist_section)
+                .collect(),
+            Contents::Yaml(documents) => {
+                let mut artists = vec![];
+                for document in documents {
+                    let artist = parse_artist_section(document)?;
+                    // YAML lists aliases on the artist itself instead of in separate entries
+                    for alias in document.list_field("Aliases").unwrap_or_default() {
+                        artists.push(Artist {
+                            name: alias,
+                            urls: vec![],
+                            alias: Some(artist.name),
+                            note: None,
+                        });
+                    }
+                    artists.push(artist);
+                }
+                Ok(artists)
+            }
+        }
+        .map_err(|err| err.with_path(&self.path))
+    }
+}
+
+/// How to handle broken hsmusic-data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Fail if anything is broken, reporting every broken file at once.
+    Strict,
+    /// Skip broken files, albums and tracks, recording them as warnings.
+    Lenient,
+}
+
+impl FromStr for LoadMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "lenient" => Ok(Self::Lenient),
+            _ => Err(anyhow!("Bad load mode {}!", s)),
+        }
+    }
 }
 
 fn get_duration_in_seconds(duration: &str) -> usize {
@@ -270,21 +600,46 @@ pub fn parse_track<'a>(
     color: &'a str,
     track_num: usize,
 ) -> Result<Track<'a>> {
-    let name = get_basic_field(section, "Track").context("missing Track")?;
+    Ok(parse_track_section(
+        TextSection {
+            text: section,
+            index: 0,
+            line: 1,
+        },
+        group,
+        group_color,
+        track_art_date,
+        artists,
+        color,
+        track_num,
+    )?)
+}
+
+fn parse_track_section<'a>(
+    section: impl Section<'a>,
+    group: &'a str,
+    group_color: &'a str,
+    track_art_date: NaiveDate,
+    artists: &Option<Vec<Contributor<'a>>>,
+    color: &'a str,
+    track_num: usize,
+) -> Result<Track<'a>, ParseError> {
+    let name = required_field(section, "Track")?;
     let name = decode_html(name).unwrap_or_else(|_| name.to_string());
-    let directory = get_basic_field(section, "Directory")
+    let directory = section
+        .basic_field("Directory")
         .map(From::from)
         .unwrap_or_else(|| get_kebab_case(&name).into());
     let original_date = get_date_field(section, "Original Date")?;
     Ok(Track {
         name,
-        commentary: get_multiline_field(section, "Commentary"),
-        lyrics: get_multiline_field(section, "Lyrics"),
+        commentary: section.multiline_field("Commentary"),
+        lyrics: section.multiline_field("Lyrics"),
         original_date,
         cover_art_date: get_date_field(section, "Cover Art Date")?
             .or(original_date)
             .unwrap_or(track_art_date),
-        references: get_list_field(section, "References").unwrap_or_default(),
+        references: section.list_field("References").unwrap_or_default(),
         artists: get_contribution_field(section, "Artists")
             .or_else(|| get_contribution_field(section, "Artist"))
             .or_else(|| artists.clone()),
@@ -295,12 +650,12 @@ pub fn parse_track<'a>(
             Some(cover_artists) => Some(cover_artists),
             None => None,
         },
-        art_tags: get_list_field(section, "Art Tags").unwrap_or_default(),
+        art_tags: section.list_field("Art Tags").unwrap_or_default(),
         contributors: get_contribution_field(section, "Contributors").unwrap_or_default(),
         directory,
-        aka: get_basic_field(section, "AKA"),
-        duration: get_duration_in_seconds(get_basic_field(section, "Duration").unwrap_or("0:00")),
-        urls: get_list_field(section, "URLs").unwrap_or_default(),
+        aka: section.basic_field("AKA"),
+        duration: get_duration_in_seconds(section.basic_field("Duration").unwrap_or("0:00")),
+        urls: section.list_field("URLs").unwrap_or_default(),
         group,
         color: if !group.is_empty() {
             group_color
@@ -312,17 +667,27 @@ pub fn parse_track<'a>(
 }
 
 pub fn parse_album(string: &str) -> Result<Album> {
-    let mut tracks = vec![];
+    let sections = text_sections(string);
+    Ok(parse_album_sections(
+        sections[0],
+        sections[1..].iter().copied(),
+        None,
+    )?)
+}
 
-    let regex = &*SPLIT_REGEX;
-    let mut split = regex.split(string);
-    let album_section = split.next().context("no album section")?;
+fn parse_album_sections<'a, S: Section<'a>>(
+    album_section: S,
+    sections: impl IntoIterator<Item = S>,
+    mut warnings: Option<&mut Vec<ParseError>>,
+) -> Result<Album<'a>, ParseError> {
+    let mut tracks = vec![];
 
-    let name = get_basic_field(album_section, "Album").context("no Album")?;
+    let name = required_field(album_section, "Album")?;
     let artists = get_contribution_field(album_section, "Artists")
         .or_else(|| get_contribution_field(album_section, "Artist"));
-    let date = get_date_field(album_section, "Date")?.context("no Date")?;
-    let color = get_basic_field(album_section, "FG").unwrap_or("#0088ff");
+    let date = get_date_field(album_section, "Date")?
+        .ok_or_else(|| ParseError::missing(album_section, "Date"))?;
+    let color = album_section.basic_field("FG").unwrap_or("#0088ff");
     let track_art_date = get_date_field(album_section, "Track Art Date")?.unwrap_or(date);
 
     let mut uses_groups = false;
@@ -330,17 +695,17 @@ pub fn parse_album(string: &str) -> Result<Album> {
     let mut group_color = color;
     let mut track_num = 1;
 
-    for section in split {
-        if section.trim().is_empty() {
+    for section in sections {
+        if section.is_empty() {
             continue;
         }
 
-        if let Some(group_name) = get_basic_field(section, "Group") {
+        if let Some(group_name) = section.basic_field("Group") {
             group = group_name;
-            group_color = get_basic_field(section, "FG").unwrap_or(color);
+            group_color = section.basic_field("FG").unwrap_or(color);
             uses_groups = true;
         } else {
-            tracks.push(parse_track(
+            let track = parse_track_section(
                 section,
                 group,
                 group_color,
@@ -348,7 +713,14 @@ pub fn parse_album(string: &str) -> Result<Album> {
                 &artists,
                 color,
                 track_num,
-            )?);
+            );
+
+            match (track, warnings.as_deref_mut()) {
+                (Ok(track), _) => tracks.push(track),
+                (Err(err), Some(warnings)) => warnings.push(err),
+                (Err(err), None) => return Err(err),
+            }
+
             track_num += 1;
         }
     }
@@ -360,16 +732,17 @@ pub fn parse_album(string: &str) -> Result<Album> {
         track_art_date,
         cover_art_date: get_date_field(album_section, "Cover Art Date")?.unwrap_or(date),
         cover_artists: get_contribution_field(album_section, "Cover Art"),
-        has_track_art: get_basic_field(album_section, "Has Track Art") != Some("no"),
+        has_track_art: album_section.basic_field("Has Track Art") != Some("no"),
         track_cover_artists: get_contribution_field(album_section, "Track Art"),
-        art_tags: get_list_field(album_section, "Art Tags").unwrap_or_default(),
-        commentary: get_multiline_field(album_section, "Commentary"),
-        urls: get_list_field(album_section, "URLs").unwrap_or_default(),
-        groups: get_list_field(album_section, "Groups").unwrap_or_default(),
-        directory: get_basic_field(album_section, "Directory")
+        art_tags: album_section.list_field("Art Tags").unwrap_or_default(),
+        commentary: album_section.multiline_field("Commentary"),
+        urls: album_section.list_field("URLs").unwrap_or_default(),
+        groups: album_section.list_field("Groups").unwrap_or_default(),
+        directory: album_section
+            .basic_field("Directory")
             .map(From::from)
             .unwrap_or_else(|| get_kebab_case(name).into()),
-        is_major_release: get_basic_field(album_section, "Major Release") == Some("yes"),
+        is_major_release: album_section.basic_field("Major Release") == Some("yes"),
         color,
         uses_groups,
         tracks,
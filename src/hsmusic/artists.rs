@@ -0,0 +1,104 @@
+//! the artist database, for resolving contributors to canonical artists
+use super::{get_kebab_case, Album, Artist, Contributor, DataFile};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Finds the artist file in hsmusic-data, if there is one.
+pub fn read_artists_file(hsmusic_data: impl AsRef<Path>) -> Result<Option<DataFile>> {
+    for name in ["artists.yaml", "artists.yml", "artists.txt"] {
+        let path = hsmusic_data.as_ref().join(name);
+        if path.is_file() {
+            return Ok(Some(DataFile::read(path)?));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Every artist in hsmusic-data, indexed by name, alias and directory.
+#[derive(Debug, Default)]
+pub struct Artists<'a> {
+    artists: Vec<Artist<'a>>,
+    by_name: HashMap<&'a str, usize>,
+    by_directory: HashMap<String, usize>,
+}
+
+impl<'a> Artists<'a> {
+    pub fn new(artists: Vec<Artist<'a>>) -> Self {
+        let mut by_name = HashMap::new();
+        let mut by_directory = HashMap::new();
+
+        for (i, artist) in artists.iter().enumerate() {
+            by_name.entry(artist.name).or_insert(i);
+            by_directory.entry(get_kebab_case(artist.name)).or_insert(i);
+        }
+
+        Self {
+            artists,
+            by_name,
+            by_directory,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Artist<'a>> {
+        self.artists.iter().filter(|x| x.alias.is_none())
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Artist<'a>> {
+        let i = match name.strip_prefix("artist:") {
+            Some(directory) => self.by_directory.get(directory),
+            None => self.by_name.get(name),
+        }?;
+        Some(&self.artists[*i])
+    }
+
+    /// Looks up an artist by name, alias or `artist:directory` reference, following aliases to
+    /// the canonical artist.
+    pub fn get(&self, name: &str) -> Option<&Artist<'a>> {
+        let artist = self.lookup(name)?;
+        match artist.alias {
+            Some(alias) => self.lookup(alias).or(Some(artist)),
+            None => Some(artist),
+        }
+    }
+
+    /// The canonical name for `name`, or `name` itself if it isn't a known artist.
+    pub fn canonical_name<'b>(&self, name: &'b str) -> &'b str
+    where
+        'a: 'b,
+    {
+        self.get(name).map_or(name, |x| x.name)
+    }
+
+    fn resolve_contributors<'b>(&self, contributors: &mut [Contributor<'b>])
+    where
+        'a: 'b,
+    {
+        for contributor in contributors {
+            contributor.who = self.canonical_name(contributor.who);
+        }
+    }
+
+    /// Rewrites every contributor in `album` to use canonical artist names.
+    pub fn resolve_album<'b>(&self, album: &mut Album<'b>)
+    where
+        'a: 'b,
+    {
+        for contributors in album
+            .artists
+            .iter_mut()
+            .chain(&mut album.cover_artists)
+            .chain(&mut album.track_cover_artists)
+        {
+            self.resolve_contributors(contributors);
+        }
+
+        for track in &mut album.tracks {
+            for contributors in track.artists.iter_mut().chain(&mut track.cover_artists) {
+                self.resolve_contributors(contributors);
+            }
+            self.resolve_contributors(&mut track.contributors);
+        }
+    }
+}
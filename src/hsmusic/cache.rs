@@ -0,0 +1,153 @@
+//! on-disk cache of parsed albums, so unchanged hsmusic-data doesn't need to be parsed again
+use super::{owned, DataFile, LoadMode, ParseError, ParseErrors};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{metadata, read, read_dir, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Bump this whenever the parser changes in a way that affects its output.
+const CACHE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    mtime: SystemTime,
+    hash: [u8; 32],
+    /// The album as parsed in lenient mode, along with any tracks that were skipped.
+    result: Result<(owned::Album, Vec<ParseError>), ParseError>,
+}
+
+impl Entry {
+    fn parse(path: &Path, mtime: SystemTime, hash: [u8; 32], bytes: Vec<u8>) -> Self {
+        let result = String::from_utf8(bytes)
+            .map_err(|_| ParseError::new("isn't valid UTF-8").with_path(path))
+            .and_then(|string| DataFile::from_string(path, string))
+            .and_then(|file| {
+                let mut warnings = vec![];
+                let album = file.parse_album_lenient(&mut warnings)?;
+                Ok(((&album).into(), warnings))
+            });
+
+        Self {
+            mtime,
+            hash,
+            result,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cache {
+    version: (u32, String),
+    entries: HashMap<PathBuf, Entry>,
+}
+
+impl Cache {
+    fn version() -> (u32, String) {
+        (CACHE_VERSION, env!("CARGO_PKG_VERSION").to_string())
+    }
+
+    fn new() -> Self {
+        Self {
+            version: Self::version(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Reads the cache at `path`, starting over if it's missing, corrupt or outdated.
+    fn read(path: &Path) -> Self {
+        File::open(path)
+            .ok()
+            .and_then(|file| bincode::deserialize_from(BufReader::new(file)).ok())
+            .filter(|cache: &Self| cache.version == Self::version())
+            .unwrap_or_else(Self::new)
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create cache {}", path.display()))?;
+        bincode::serialize_into(BufWriter::new(file), self).context("failed to write cache")?;
+        Ok(())
+    }
+}
+
+/// Loads every album in `dir`, only parsing files that have changed since they were cached in
+/// `cache_path`. Without a cache path, everything is parsed.
+pub fn load_albums(
+    dir: impl AsRef<Path>,
+    cache_path: Option<&Path>,
+    mode: LoadMode,
+) -> Result<owned::LoadedAlbums> {
+    let mut old = cache_path.map(Cache::read).unwrap_or_else(Cache::new);
+    let mut cache = Cache::new();
+    let mut paths = vec![];
+    // Files that can't be read aren't cached, so they're tried again next time
+    let mut errors = vec![];
+
+    for ent in read_dir(dir)? {
+        let path = ent?.path();
+        let unreadable = |err: std::io::Error| {
+            ParseError::new(format!("failed to read: {}", err)).with_path(&path)
+        };
+        let mtime = match metadata(&path).and_then(|x| x.modified()) {
+            Ok(mtime) => mtime,
+            Err(err) => {
+                errors.push(unreadable(err));
+                continue;
+            }
+        };
+
+        let entry = match old.entries.remove(&path) {
+            Some(entry) if entry.mtime == mtime => entry,
+            cached => {
+                let bytes = match read(&path) {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        errors.push(unreadable(err));
+                        continue;
+                    }
+                };
+                let hash = Sha256::digest(&bytes).into();
+
+                match cached {
+                    Some(entry) if entry.hash == hash => Entry { mtime, ..entry },
+                    _ => Entry::parse(&path, mtime, hash, bytes),
+                }
+            }
+        };
+
+        cache.entries.insert(path.clone(), entry);
+        paths.push(path);
+    }
+
+    if let Some(cache_path) = cache_path {
+        cache.write(cache_path)?;
+    }
+
+    let mut albums = vec![];
+    let mut warnings = vec![];
+
+    for path in paths {
+        match cache.entries.remove(&path).unwrap().result {
+            Ok((_, album_warnings)) if mode == LoadMode::Strict && !album_warnings.is_empty() => {
+                errors.extend(album_warnings)
+            }
+            Ok((album, album_warnings)) => {
+                albums.push(album);
+                warnings.extend(album_warnings);
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+
+    match mode {
+        LoadMode::Strict if !errors.is_empty() => Err(ParseErrors(errors).into()),
+        _ => {
+            warnings.extend(errors);
+            Ok(owned::LoadedAlbums { albums, warnings })
+        }
+    }
+}
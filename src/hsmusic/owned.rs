@@ -0,0 +1,225 @@
+//! owned versions of the parsed types, for storing and caching a catalog
+use super::ParseError;
+use chrono::naive::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contributor {
+    pub who: String,
+    pub what: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artist {
+    pub name: String,
+    pub urls: Vec<String>,
+    pub alias: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Track {
+    pub name: String,
+    pub commentary: Option<String>,
+    pub lyrics: Option<String>,
+    pub original_date: Option<NaiveDate>,
+    pub cover_art_date: NaiveDate,
+    pub references: Vec<String>,
+    pub artists: Option<Vec<Contributor>>,
+    pub cover_artists: Option<Vec<Contributor>>,
+    pub art_tags: Vec<String>,
+    pub contributors: Vec<Contributor>,
+    pub directory: String,
+    pub aka: Option<String>,
+    pub duration: usize,
+    pub urls: Vec<String>,
+    pub group: String,
+    pub color: String,
+    pub track_num: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Album {
+    pub name: String,
+    pub artists: Option<Vec<Contributor>>,
+    pub date: NaiveDate,
+    pub track_art_date: NaiveDate,
+    pub cover_art_date: NaiveDate,
+    pub cover_artists: Option<Vec<Contributor>>,
+    pub has_track_art: bool,
+    pub track_cover_artists: Option<Vec<Contributor>>,
+    pub art_tags: Vec<String>,
+    pub commentary: Option<String>,
+    pub urls: Vec<String>,
+    pub groups: Vec<String>,
+    pub directory: String,
+    pub is_major_release: bool,
+    pub color: String,
+    pub uses_groups: bool,
+    pub tracks: Vec<Track>,
+}
+
+/// Owned albums, along with everything that was skipped to get them.
+#[derive(Debug, Clone)]
+pub struct LoadedAlbums {
+    pub albums: Vec<Album>,
+    pub warnings: Vec<ParseError>,
+}
+
+fn strings(x: &[&str]) -> Vec<String> {
+    x.iter().map(|x| x.to_string()).collect()
+}
+
+fn strs(x: &[String]) -> Vec<&str> {
+    x.iter().map(|x| &x[..]).collect()
+}
+
+fn contributors(x: &Option<Vec<super::Contributor>>) -> Option<Vec<Contributor>> {
+    x.as_ref().map(|x| x.iter().map(From::from).collect())
+}
+
+fn contributor_refs(x: &Option<Vec<Contributor>>) -> Option<Vec<super::Contributor<'_>>> {
+    x.as_ref()
+        .map(|x| x.iter().map(Contributor::as_contributor).collect())
+}
+
+impl From<&super::Contributor<'_>> for Contributor {
+    fn from(x: &super::Contributor) -> Self {
+        Self {
+            who: x.who.to_string(),
+            what: x.what.map(From::from),
+        }
+    }
+}
+
+impl Contributor {
+    pub fn as_contributor(&self) -> super::Contributor<'_> {
+        super::Contributor {
+            who: &self.who,
+            what: self.what.as_deref(),
+        }
+    }
+}
+
+impl From<&super::Artist<'_>> for Artist {
+    fn from(x: &super::Artist) -> Self {
+        Self {
+            name: x.name.to_string(),
+            urls: strings(&x.urls),
+            alias: x.alias.map(From::from),
+            note: x.note.clone(),
+        }
+    }
+}
+
+impl Artist {
+    pub fn as_artist(&self) -> super::Artist<'_> {
+        super::Artist {
+            name: &self.name,
+            urls: strs(&self.urls),
+            alias: self.alias.as_deref(),
+            note: self.note.clone(),
+        }
+    }
+}
+
+impl From<&super::Track<'_>> for Track {
+    fn from(x: &super::Track) -> Self {
+        Self {
+            name: x.name.clone(),
+            commentary: x.commentary.clone(),
+            lyrics: x.lyrics.clone(),
+            original_date: x.original_date,
+            cover_art_date: x.cover_art_date,
+            references: strings(&x.references),
+            artists: contributors(&x.artists),
+            cover_artists: contributors(&x.cover_artists),
+            art_tags: strings(&x.art_tags),
+            contributors: x.contributors.iter().map(From::from).collect(),
+            directory: x.directory.to_string(),
+            aka: x.aka.map(From::from),
+            duration: x.duration,
+            urls: strings(&x.urls),
+            group: x.group.to_string(),
+            color: x.color.to_string(),
+            track_num: x.track_num,
+        }
+    }
+}
+
+impl Track {
+    pub fn as_track(&self) -> super::Track<'_> {
+        super::Track {
+            name: self.name.clone(),
+            commentary: self.commentary.clone(),
+            lyrics: self.lyrics.clone(),
+            original_date: self.original_date,
+            cover_art_date: self.cover_art_date,
+            references: strs(&self.references),
+            artists: contributor_refs(&self.artists),
+            cover_artists: contributor_refs(&self.cover_artists),
+            art_tags: strs(&self.art_tags),
+            contributors: self
+                .contributors
+                .iter()
+                .map(Contributor::as_contributor)
+                .collect(),
+            directory: Cow::Borrowed(&self.directory),
+            aka: self.aka.as_deref(),
+            duration: self.duration,
+            urls: strs(&self.urls),
+            group: &self.group,
+            color: &self.color,
+            track_num: self.track_num,
+        }
+    }
+}
+
+impl From<&super::Album<'_>> for Album {
+    fn from(x: &super::Album) -> Self {
+        Self {
+            name: x.name.to_string(),
+            artists: contributors(&x.artists),
+            date: x.date,
+            track_art_date: x.track_art_date,
+            cover_art_date: x.cover_art_date,
+            cover_artists: contributors(&x.cover_artists),
+            has_track_art: x.has_track_art,
+            track_cover_artists: contributors(&x.track_cover_artists),
+            art_tags: strings(&x.art_tags),
+            commentary: x.commentary.clone(),
+            urls: strings(&x.urls),
+            groups: strings(&x.groups),
+            directory: x.directory.to_string(),
+            is_major_release: x.is_major_release,
+            color: x.color.to_string(),
+            uses_groups: x.uses_groups,
+            tracks: x.tracks.iter().map(From::from).collect(),
+        }
+    }
+}
+
+impl Album {
+    pub fn as_album(&self) -> super::Album<'_> {
+        super::Album {
+            name: &self.name,
+            artists: contributor_refs(&self.artists),
+            date: self.date,
+            track_art_date: self.track_art_date,
+            cover_art_date: self.cover_art_date,
+            cover_artists: contributor_refs(&self.cover_artists),
+            has_track_art: self.has_track_art,
+            track_cover_artists: contributor_refs(&self.track_cover_artists),
+            art_tags: strs(&self.art_tags),
+            commentary: self.commentary.clone(),
+            urls: strs(&self.urls),
+            groups: strs(&self.groups),
+            directory: Cow::Borrowed(&self.directory),
+            is_major_release: self.is_major_release,
+            color: &self.color,
+            uses_groups: self.uses_groups,
+            tracks: self.tracks.iter().map(Track::as_track).collect(),
+        }
+    }
+}
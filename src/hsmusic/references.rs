@@ -0,0 +1,68 @@
+//! resolving track references, and indexing which tracks reference each track
+use super::{Album, Track};
+use std::collections::HashMap;
+
+/// Every track in the catalog, indexed by what it references and what references it.
+#[derive(Debug)]
+pub struct References<'b, 'a> {
+    by_directory: HashMap<&'b str, &'b Track<'a>>,
+    by_name: HashMap<&'b str, &'b Track<'a>>,
+    referenced_by: HashMap<&'b str, Vec<&'b Track<'a>>>,
+}
+
+impl<'b, 'a> References<'b, 'a> {
+    pub fn new(albums: &'b [Album<'a>]) -> Self {
+        let mut by_directory = HashMap::new();
+        let mut by_name = HashMap::new();
+
+        for track in albums.iter().flat_map(|x| &x.tracks) {
+            by_directory.entry(&*track.directory).or_insert(track);
+            by_name.entry(&track.name[..]).or_insert(track);
+        }
+
+        let mut references = Self {
+            by_directory,
+            by_name,
+            referenced_by: HashMap::new(),
+        };
+
+        for track in albums.iter().flat_map(|x| &x.tracks) {
+            for referenced in references.references(track) {
+                let referenced_by = references
+                    .referenced_by
+                    .entry(&*referenced.directory)
+                    .or_default();
+                if !referenced_by.iter().any(|x| x.directory == track.directory) {
+                    referenced_by.push(track);
+                }
+            }
+        }
+
+        references
+    }
+
+    /// Looks up a reference, either a `track:directory` or a track name.
+    pub fn resolve(&self, reference: &str) -> Option<&'b Track<'a>> {
+        match reference.strip_prefix("track:") {
+            Some(directory) => self.by_directory.get(directory.trim()),
+            None => self.by_name.get(reference.trim()),
+        }
+        .copied()
+    }
+
+    /// The catalog tracks `track` references. References that can't be resolved are skipped.
+    pub fn references(&self, track: &Track) -> Vec<&'b Track<'a>> {
+        track
+            .references
+            .iter()
+            .filter_map(|x| self.resolve(x))
+            .collect()
+    }
+
+    /// The catalog tracks that reference `track`.
+    pub fn referenced_by(&self, track: &Track) -> &[&'b Track<'a>] {
+        self.referenced_by
+            .get(&*track.directory)
+            .map_or(&[], |x| &x[..])
+    }
+}
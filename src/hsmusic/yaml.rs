@@ -0,0 +1,282 @@
+//! multi-document YAML, as used by current hsmusic-data
+use super::{ParseError, Section};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_yaml::Value;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+enum Field {
+    Scalar(String),
+    List(Vec<String>),
+}
+
+#[derive(Debug)]
+pub struct Document {
+    index: usize,
+    line: usize,
+    fields: HashMap<String, (Field, usize)>,
+}
+
+fn scalar(value: Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s),
+        Value::Number(n) => Some(n.to_string()),
+        // the legacy format spells booleans as yes/no
+        Value::Bool(true) => Some("yes".to_string()),
+        Value::Bool(false) => Some("no".to_string()),
+        _ => None,
+    }
+}
+
+impl Document {
+    fn parse(string: &str, index: usize, line: usize) -> Result<Self, ParseError> {
+        let error = |message: String, line| ParseError {
+            section: Some(index),
+            line: Some(line),
+            ..ParseError::new(message)
+        };
+
+        let mapping = match serde_yaml::from_str(string) {
+            Ok(Value::Mapping(mapping)) => mapping,
+            Ok(Value::Null) => Default::default(),
+            Ok(_) => return Err(error("document isn't a mapping".to_string(), line)),
+            Err(err) => {
+                let err_line = err.location().map_or(line, |x| line + x.line() - 1);
+                return Err(error(err.to_string(), err_line));
+            }
+        };
+
+        let mut fields = HashMap::new();
+        for (key, value) in mapping {
+            let key = scalar(key).ok_or_else(|| error("bad key".to_string(), line))?;
+            let field = match value {
+                Value::Sequence(seq) => Field::List(seq.into_iter().filter_map(scalar).collect()),
+                other => match scalar(other) {
+                    Some(s) => Field::Scalar(s),
+                    None => continue,
+                },
+            };
+            let key_line = string
+                .lines()
+                .position(|x| x.starts_with(&format!("{}:", key)))
+                .map_or(line, |i| line + i);
+            fields.insert(key, (field, key_line));
+        }
+
+        Ok(Self {
+            index,
+            line,
+            fields,
+        })
+    }
+
+    /// Maps the legacy field names used by the parser onto their YAML equivalents.
+    fn yaml_name<'a>(&self, name: &'a str) -> &'a str {
+        match name {
+            "FG" => "Color",
+            "Group" => "Section",
+            "Cover Art" => "Cover Artists",
+            "Track Art" if self.fields.contains_key("Album") => "Default Track Cover Artists",
+            "Track Art" => "Cover Artists",
+            "Track Art Date" => "Default Track Cover Art Date",
+            "Original Date" => "Date First Released",
+            "References" => "Referenced Tracks",
+            "AKA" => "Originally Released As",
+            "Note" => "Context Notes",
+            other => other,
+        }
+    }
+
+    fn get_with_line(&self, name: &str) -> Option<&(Field, usize)> {
+        self.fields
+            .get(name)
+            .or_else(|| self.fields.get(self.yaml_name(name)))
+    }
+
+    fn get(&self, name: &str) -> Option<&Field> {
+        self.get_with_line(name).map(|(field, _)| field)
+    }
+}
+
+impl<'a> Section<'a> for &'a Document {
+    fn index(self) -> usize {
+        self.index
+    }
+
+    fn line(self) -> usize {
+        self.line
+    }
+
+    fn field_line(self, name: &str) -> Option<usize> {
+        self.get_with_line(name).map(|&(_, line)| line)
+    }
+
+    fn is_empty(self) -> bool {
+        self.fields.is_empty()
+    }
+
+    fn basic_field(self, name: &str) -> Option<&'a str> {
+        match self.get(name)? {
+            Field::Scalar(s) => Some(s),
+            Field::List(_) => None,
+        }
+    }
+
+    fn list_field(self, name: &str) -> Option<Vec<&'a str>> {
+        // YAML tracks opt out of art with a flag rather than "Track Art: none"
+        if name == "Track Art"
+            && !self.fields.contains_key("Album")
+            && self.basic_field("Has Cover Art") == Some("no")
+        {
+            return Some(vec!["none"]);
+        }
+
+        match self.get(name)? {
+            Field::Scalar(s) => Some(s.split(',').map(|x| x.trim()).collect()),
+            Field::List(list) => Some(list.iter().map(|x| &x[..]).collect()),
+        }
+    }
+
+    fn multiline_field(self, name: &str) -> Option<String> {
+        match self.get(name)? {
+            Field::Scalar(s) if !s.trim().is_empty() => Some(s.trim_end().to_string()),
+            _ => None,
+        }
+    }
+}
+
+static SPLIT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("(?m)^---[ \t]*$\n?").unwrap());
+
+pub fn is_yaml(string: &str) -> bool {
+    SPLIT_REGEX.is_match(string)
+}
+
+pub fn parse_documents(string: &str) -> Result<Vec<Document>, ParseError> {
+    let mut documents = vec![];
+    let mut start = 0;
+    let mut line = 1;
+
+    let ends = SPLIT_REGEX
+        .find_iter(string)
+        .map(|x| (x.start(), x.end()))
+        .chain(std::iter::once((string.len(), string.len())));
+
+    for (end, next) in ends {
+        let text = &string[start..end];
+        if !text.trim().is_empty() {
+            documents.push(Document::parse(text, documents.len(), line)?);
+        }
+        line += string[start..next].matches('\n').count();
+        start = next;
+    }
+
+    Ok(documents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Contents, DataFile};
+    use super::*;
+
+    const ALBUM: &str = "\
+Album: Homestuck Vol. 5
+Directory: homestuck-vol-5
+Default Track Cover Artists: Xamag
+---
+Section: Act 5
+---
+Track: Showtime (Imp Strife Mix)
+Has Cover Art: false
+Artists:
+- Toby Fox
+- Jit
+---
+Track: Descend
+Artists: Toby Fox
+Referenced Tracks:
+- Showtime (Original Mix)
+";
+
+    const ARTISTS: &str = "\
+Artist: Toby Fox
+Aliases:
+- Radiation
+---
+Artist: Jit
+";
+
+    #[test]
+    fn maps_legacy_field_names() {
+        let documents = parse_documents(ALBUM).unwrap();
+        let [album, section, showtime, descend] = match &documents[..] {
+            [a, b, c, d] => [a, b, c, d],
+            documents => panic!("{} documents", documents.len()),
+        };
+
+        assert_eq!(album.list_field("Track Art"), Some(vec!["Xamag"]));
+        assert_eq!(section.basic_field("Group"), Some("Act 5"));
+        assert_eq!(showtime.list_field("Track Art"), Some(vec!["none"]));
+        assert_eq!(
+            showtime.list_field("Artists"),
+            Some(vec!["Toby Fox", "Jit"])
+        );
+        assert_eq!(descend.list_field("Track Art"), None);
+        assert_eq!(descend.list_field("Artists"), Some(vec!["Toby Fox"]));
+        assert_eq!(
+            descend.list_field("References"),
+            Some(vec!["Showtime (Original Mix)"])
+        );
+    }
+
+    #[test]
+    fn counts_lines_across_documents() {
+        let documents = parse_documents(ALBUM).unwrap();
+        let lines: Vec<_> = documents.iter().map(|x| (x.index(), x.line())).collect();
+        assert_eq!(lines, [(0, 1), (1, 5), (2, 7), (3, 13)]);
+        assert_eq!(documents[2].field_line("Artists"), Some(9));
+        assert_eq!(documents[3].field_line("References"), Some(15));
+    }
+
+    #[test]
+    fn reports_the_line_of_syntax_errors() {
+        let err = parse_documents(
+            "Track: Showtime\n---\nTrack: Descend\nArtists: [Toby Fox]]\nDuration: 4:17\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.section, Some(1));
+        assert_eq!(err.line, Some(4));
+    }
+
+    #[test]
+    fn detects_yaml() {
+        assert!(is_yaml(ALBUM));
+        assert!(!is_yaml(
+            "Album: Homestuck Vol. 5\n\n--------\n\nTrack: Showtime\n"
+        ));
+
+        for (path, yaml) in [("album", true), ("album.yaml", true), ("album.txt", false)] {
+            let file = DataFile::from_string(path, ALBUM.to_string()).unwrap();
+            assert_eq!(matches!(file.contents, Contents::Yaml(_)), yaml, "{}", path);
+        }
+    }
+
+    #[test]
+    fn lists_aliases_as_artists() {
+        let file = DataFile::from_string("artists.yaml", ARTISTS.to_string()).unwrap();
+        let artists: Vec<_> = file
+            .parse_artists()
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.name, x.alias))
+            .collect();
+        assert_eq!(
+            artists,
+            [
+                ("Radiation", Some("Toby Fox")),
+                ("Toby Fox", None),
+                ("Jit", None)
+            ]
+        );
+    }
+}
@@ -1,17 +1,29 @@
 use anyhow::{anyhow, Context, Error, Result};
 use locate::*;
 use lofty::{ItemKey, Picture, PictureType, Tag};
+use profile::Field;
 use rayon::prelude::*;
 use std::fmt::Write;
-use std::fs::{create_dir_all, read_dir, read_to_string, File};
+use std::fs::{create_dir_all, File};
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use walkdir::WalkDir;
 
 pub mod bandcamp;
+pub mod catalog;
+pub mod groups;
 pub mod hsmusic;
 pub mod locate;
+pub mod manual;
+pub mod markup;
+pub mod matching;
+pub mod merge;
+pub mod overrides;
+pub mod profile;
+pub mod report;
+pub mod tags;
+pub mod template;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ArtType {
@@ -37,41 +49,281 @@ pub struct ArtTypes {
     pub rest: ArtType,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArtistStyle {
+    /// All artists in one value, separated by the given string
+    Joined(String),
+    /// One value per artist, for formats and players that support it
+    Multiple,
+    /// The first artist, followed by "feat." and the rest
+    Featuring,
+}
+
+impl FromStr for ArtistStyle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "joined" => Ok(Self::Joined(", ".to_string())),
+            "multiple" => Ok(Self::Multiple),
+            "feat" | "featuring" => Ok(Self::Featuring),
+            _ => Err(anyhow!("Bad artist style {}!", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LyricsMode {
+    /// Embed lyrics in the file's lyrics tag
+    Embed,
+    /// Write an .lrc file next to each track
+    Lrc,
+    /// Write a .txt file next to each track
+    Txt,
+}
+
+impl FromStr for LyricsMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "embed" => Ok(Self::Embed),
+            "lrc" => Ok(Self::Lrc),
+            "txt" => Ok(Self::Txt),
+            _ => Err(anyhow!("Bad lyrics mode {}!", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommentarySource {
+    Track,
+    Album,
+    /// The album's commentary after the track's
+    Both,
+}
+
+impl FromStr for CommentarySource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "track" => Ok(Self::Track),
+            "album" => Ok(Self::Album),
+            "both" => Ok(Self::Both),
+            _ => Err(anyhow!("Bad commentary source {}!", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommentaryField {
+    /// The standard comment field
+    Comment,
+    /// A custom field, HSMUSIC_COMMENTARY unless the profile says otherwise
+    Custom,
+}
+
+impl FromStr for CommentaryField {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "comment" => Ok(Self::Comment),
+            "custom" => Ok(Self::Custom),
+            _ => Err(anyhow!("Bad commentary field {}!", s)),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Commentary {
+    pub source: CommentarySource,
+    pub field: CommentaryField,
+    /// Maximum length in characters, after which commentary is cut off
+    pub max_len: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Edits {
-    pub add_artists: bool,
+    pub add_artists: Option<ArtistStyle>,
+    pub add_credits: bool,
     pub add_art: Option<ArtTypes>,
     pub add_album: bool,
+    /// Number discs by the album's group sections, when adding album
+    pub add_disc_numbers: bool,
+    pub add_lyrics: Option<LyricsMode>,
+    pub add_commentary: Option<Commentary>,
+    pub add_genres: Option<groups::GroupMapping>,
+    pub add_group_subtitle: bool,
+    pub add_dates: bool,
+    /// Record the matched hsmusic album and track, and the Bandcamp URL
+    pub add_ids: bool,
+    /// Add track, album and artist links, keeping only these hosts if any are given
+    pub add_urls: Option<Vec<String>>,
+    /// Add the track's color, as a hex code
+    pub add_color: bool,
+    /// Add the tracks each track references, and the tracks referencing it
+    pub add_references: bool,
+    /// Warn when a file's length differs from the hsmusic track's by more than this many seconds
+    pub check_duration: Option<u64>,
+    /// Which fields hsmusic data is written to
+    pub profile: profile::Profile,
+    /// How to merge with tags files already have
+    pub merge: merge::Merge,
+    /// Templates for matching files without tags by their path, tried in order
+    pub path_templates: Vec<template::PathTemplate>,
+    /// How confident a fuzzy match has to be, from 0 to 1, before it's used
+    pub min_confidence: f64,
+    /// Files matched by hand, which skip the matcher
+    pub manual_matches: manual::ManualMatches,
+}
+
+fn write_lyrics_sidecar(
+    out_path: &Path,
+    mode: LyricsMode,
+    album: &hsmusic::Album,
+    track: &hsmusic::Track,
+    lyrics: &str,
+) -> Result<()> {
+    let (extension, contents) = match mode {
+        LyricsMode::Lrc => {
+            let mut lrc = format!("[ti:{}]\n[al:{}]\n", track.name, album.name);
+            if let Some(artists) = &track.artists {
+                let artists = artists.iter().map(|x| x.who).collect::<Vec<_>>().join(", ");
+                writeln!(lrc, "[ar:{}]", artists)?;
+            }
+            writeln!(lrc, "\n{}", lyrics)?;
+            ("lrc", lrc)
+        }
+        _ => ("txt", format!("{}\n", lyrics)),
+    };
+
+    std::fs::write(out_path.with_extension(extension), contents)
+        .context("failed to write lyrics")?;
+
+    Ok(())
+}
+
+/// Gives an untagged file a tag with its hsmusic track's title and album artists.
+fn insert_tag<'m>(
+    metadata: &'m mut lofty::TaggedFile,
+    album: &hsmusic::Album,
+    track: &hsmusic::Track,
+) -> &'m mut Tag {
+    metadata.insert_tag(Tag::new(metadata.primary_tag_type()));
+    let tag = metadata.primary_tag_mut().unwrap();
+    tag.insert_text(ItemKey::TrackTitle, track.name.to_string());
+    let album_artists = tags::album_artists(album);
+    if !album_artists.is_empty() {
+        tag.insert_text(ItemKey::AlbumArtist, album_artists.join(", "));
+    }
+    tag
+}
+
+/// The Bandcamp dump, hsmusic-data and overrides, read once for the catalog to borrow from.
+pub struct Data {
+    pub bandcamp_albums: Vec<bandcamp::Album>,
+    pub overrides: overrides::Overrides,
+    /// Broken hsmusic-data that was skipped in lenient mode
+    pub warnings: Vec<String>,
+    artists_file: Option<hsmusic::DataFile>,
+    loaded: hsmusic::owned::LoadedAlbums,
+}
+
+impl Data {
+    pub fn read(
+        bandcamp_json: &Path,
+        hsmusic_data: &Path,
+        load_mode: hsmusic::LoadMode,
+        cache: Option<&Path>,
+    ) -> Result<Self> {
+        let overrides = overrides::Overrides::load_beside(bandcamp_json)?;
+        let bandcamp_file = File::open(bandcamp_json)?;
+        let bandcamp_reader = BufReader::new(bandcamp_file);
+        let bandcamp_albums = serde_json::from_reader(bandcamp_reader)?;
+
+        let loaded = hsmusic::cache::load_albums(hsmusic_data.join("album"), cache, load_mode)?;
+        let mut warnings: Vec<_> = loaded.warnings.iter().map(|x| x.to_string()).collect();
+
+        let mut artists_file = hsmusic::artists::read_artists_file(hsmusic_data)?;
+        if let Some(file) = &artists_file {
+            let parsed = file.parse_artists().map(|_| ());
+            match parsed {
+                Err(err) if load_mode == hsmusic::LoadMode::Lenient => {
+                    warnings.push(err.to_string());
+                    artists_file = None;
+                }
+                parsed => parsed?,
+            }
+        }
+
+        Ok(Self {
+            bandcamp_albums,
+            overrides,
+            warnings,
+            artists_file,
+            loaded,
+        })
+    }
+
+    pub fn artists(&self) -> Result<hsmusic::artists::Artists<'_>> {
+        Ok(match &self.artists_file {
+            Some(file) => hsmusic::artists::Artists::new(file.parse_artists()?),
+            None => Default::default(),
+        })
+    }
+
+    /// Every album, with contributors resolved to canonical artists.
+    pub fn albums<'a>(
+        &'a self,
+        artists: &hsmusic::artists::Artists<'a>,
+    ) -> Vec<hsmusic::Album<'a>> {
+        let mut albums: Vec<_> = self.loaded.albums.iter().map(|x| x.as_album()).collect();
+        for album in &mut albums {
+            artists.resolve_album(album);
+        }
+        albums
+    }
+
+    pub fn catalog<'c>(&'c self, albums: &'c [hsmusic::Album<'c>]) -> catalog::Catalog<'c> {
+        catalog::Catalog::new(&self.bandcamp_albums, albums, self.overrides.clone())
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn add_art(
     bandcamp_json: PathBuf,
-    mut hsmusic_data: PathBuf,
+    hsmusic_data: PathBuf,
     hsmusic_media: PathBuf,
+    load_mode: hsmusic::LoadMode,
+    cache: Option<PathBuf>,
     edits: Edits,
     verbose: bool,
+    dry_run: bool,
     in_dir: PathBuf,
     out_dir: PathBuf,
     progress: impl Fn(usize) + Send + Sync,
-) -> Result<()> {
-    let bandcamp_file = File::open(bandcamp_json)?;
-    let bandcamp_reader = BufReader::new(bandcamp_file);
-    let bandcamp_albums: Vec<bandcamp::Album> = serde_json::from_reader(bandcamp_reader)?;
-
-    hsmusic_data.push("album");
+) -> Result<report::RunReport> {
+    let data = Data::read(&bandcamp_json, &hsmusic_data, load_mode, cache.as_deref())?;
+    let artists = data.artists()?;
+    let hsmusic_albums = data.albums(&artists);
+    let catalog = data.catalog(&hsmusic_albums);
+    let references = hsmusic::references::References::new(&hsmusic_albums);
 
-    let hsmusic_album_texts: Vec<_> = read_dir(hsmusic_data)?
-        .map(|ent| {
-            let ent = ent?;
-            Ok(read_to_string(ent.path())?)
-        })
-        .collect::<Result<_>>()?;
-
-    let hsmusic_albums: Vec<_> = hsmusic_album_texts
-        .iter()
-        .map(|x| hsmusic::parse_album(x))
-        .collect::<Result<_>>()?;
+    let resolve_reference = |kind: &str, reference: &str| match kind {
+        "track" => references
+            .resolve(&format!("track:{}", reference))
+            .map(|x| x.name.clone()),
+        "album" => hsmusic_albums
+            .iter()
+            .find(|x| x.directory == reference)
+            .map(|x| x.name.to_string()),
+        "artist" => artists
+            .get(&format!("artist:{}", reference))
+            .map(|x| x.name.to_string()),
+        _ => None,
+    };
 
     let entries: Vec<_> = WalkDir::new(&in_dir)
         .into_iter()
@@ -84,135 +336,365 @@ pub fn add_art(
         })
         .collect::<std::result::Result<_, _>>()?;
     let entries_count = entries.len();
-    let mut errors: Vec<_> = entries
-        .into_par_iter()
-        .map(|entry| {
-            progress(entries_count);
+    // Each step sets the outcome the file has if that step fails.
+    let process_file = |entry: &walkdir::DirEntry,
+                        report: &mut report::MatchReport|
+     -> Result<()> {
+        let in_path = entry.path();
+        let rel_path = in_path.strip_prefix(&in_dir)?;
+        let out_path = out_dir.join(&rel_path);
 
-            let in_path = entry.path();
-            let rel_path = in_path.strip_prefix(&in_dir)?;
-            let out_path = out_dir.join(&rel_path);
+        if !dry_run {
+            report.outcome = report::Outcome::FailedWrite;
 
             if let Some(parent) = out_path.parent() {
                 create_dir_all(parent)?;
             }
 
-            println!("{:?} -> {:?}", in_path, out_path);
+            eprintln!("{:?} -> {:?}", in_path, out_path);
 
             if out_path != in_path {
                 std::fs::copy(&in_path, &out_path)?;
             }
+        }
+
+        if let Ok(mut metadata) = tags::read_from_path(&in_path) {
+            let actual_duration = metadata.properties().duration().as_secs();
+            let duration = Some(actual_duration).filter(|&x| x > 0);
+
+            report.outcome = report::Outcome::Unmatched;
+            let has_tag = metadata.first_tag().is_some();
+            if let Some(tag) = metadata.first_tag() {
+                report.album = tag.get_string(&ItemKey::AlbumTitle).map(str::to_string);
+                report.title = tag.get_string(&ItemKey::TrackTitle).map(str::to_string);
+                report.track_num = tag
+                    .get_string(&ItemKey::TrackNumber)
+                    .and_then(|x| x.parse().ok());
+            }
+            let mut created = false;
 
-            if let Ok(mut metadata) = lofty::read_from_path(&in_path, false) {
-                let info = if let Some(tag) = metadata.first_tag_mut() {
-                    if let (Some(album_name), Some(track_num), Some(title)) = (
-                        tag.get_string(&ItemKey::AlbumTitle),
-                        tag.get_string(&ItemKey::TrackNumber),
-                        tag.get_string(&ItemKey::TrackTitle),
-                    ) {
-                        let track_num = track_num.parse()?;
-
-                        let (album, track) = find_hsmusic_from_album_track(
-                            album_name,
-                            title,
-                            track_num,
-                            &bandcamp_albums,
-                            &hsmusic_albums,
+            let manual = edits
+                .manual_matches
+                .lookup(rel_path, in_path)?
+                .map(|(album, track)| {
+                    find_hsmusic_from_ids(album, track, &catalog).ok_or_else(|| {
+                        anyhow!(
+                            "manual match {}/{} for {:?} isn't in hsmusic-data",
+                            album,
+                            track,
+                            in_path
                         )
-                        .with_context(|| {
-                            format!("failed to find hsmusic track for {:?}", in_path)
-                        })?;
+                    })
+                })
+                .transpose()?;
 
-                        Some((tag, track_num, album, track))
-                    } else {
-                        None
-                    }
-                } else if let Some((album, track)) = in_path
-                    .file_stem()
-                    .and_then(|x| x.to_str())
-                    .and_then(|x| x.split_once(" - "))
-                    .and_then(|(_artist, title)| find_hsmusic_from_title(title, &hsmusic_albums))
+            let info = if let Some((album, track)) = manual {
+                report.strategy = Some(report::Strategy::Manual);
+                if metadata.first_tag_mut().is_none() {
+                    insert_tag(&mut metadata, album, track);
+                    created = true;
+                }
+                let tag = metadata.first_tag_mut().unwrap();
+                Some((tag, track.track_num, album, track))
+            } else if let Some(tag) = metadata.first_tag_mut() {
+                if let Some((album, track)) = tags::read_ids(tag, &edits.profile)
+                    .and_then(|(album, track)| find_hsmusic_from_ids(&album, &track, &catalog))
                 {
-                    metadata.insert_tag(Tag::new(metadata.primary_tag_type()));
-                    let tag = metadata.primary_tag_mut().unwrap();
-                    tag.insert_text(ItemKey::TrackTitle, track.name.to_string());
-                    tag.insert_text(ItemKey::AlbumArtist, "Homestuck".to_string());
-                    Some((tag, track.track_num, album, track))
+                    let track_num = tag
+                        .get_string(&ItemKey::TrackNumber)
+                        .and_then(|x| x.parse().ok())
+                        .unwrap_or(track.track_num);
+                    report.strategy = Some(report::Strategy::Ids);
+
+                    Some((tag, track_num, album, track))
+                } else if let (Some(album_name), Some(track_num), Some(title)) = (
+                    tag.get_string(&ItemKey::AlbumTitle),
+                    tag.get_string(&ItemKey::TrackNumber),
+                    tag.get_string(&ItemKey::TrackTitle),
+                ) {
+                    let track_num = track_num.parse()?;
+                    let query = matching::Query {
+                        title,
+                        track_num: Some(track_num),
+                        duration,
+                    };
+
+                    let ((album, track), confidence) = find_hsmusic_from_album_track(
+                        album_name,
+                        &query,
+                        edits.min_confidence,
+                        &catalog,
+                    )
+                    .with_context(|| format!("failed to find hsmusic track for {:?}", in_path))?;
+                    report.strategy = Some(report::Strategy::Tags);
+                    report.confidence = confidence;
+
+                    Some((tag, track_num, album, track))
                 } else {
                     None
+                }
+            } else if let Some((path_info, ((album, track), confidence))) = find_hsmusic_from_path(
+                rel_path,
+                &edits.path_templates,
+                duration,
+                edits.min_confidence,
+                &catalog,
+            )
+            .with_context(|| format!("failed to find hsmusic track for {:?}", in_path))?
+            {
+                report.strategy = Some(report::Strategy::Path);
+                report.confidence = confidence;
+                report.album = path_info.album;
+                report.title = path_info.title;
+                report.track_num = path_info.track_num;
+
+                let tag = insert_tag(&mut metadata, album, track);
+                created = true;
+                Some((tag, track.track_num, album, track))
+            } else {
+                None
+            };
+
+            if let Some((tag, track_num, album, track)) = info {
+                report.outcome = report::Outcome::Failed;
+
+                if verbose {
+                    eprintln!("hsmusic: {:?} - {:?}", album.name, track.name);
+                }
+
+                report.hsmusic_album = Some(album.name.to_string());
+                report.hsmusic_track = Some(track.name.clone());
+
+                let original = tag.clone();
+                let before = if created {
+                    Tag::new(tag.tag_type())
+                } else {
+                    original.clone()
                 };
+                let mut sidecar = None;
 
-                if let Some((tag, track_num, album, track)) = info {
-                    if verbose {
-                        println!("hsmusic: {:?} - {:?}", album.name, track.name);
+                if edits.add_art.is_some() {
+                    if let Some(ArtTypes { first, rest }) = edits.add_art {
+                        let track_num = if edits.add_album {
+                            track.track_num
+                        } else {
+                            track_num
+                        };
+                        let art = if track_num <= 1 { first } else { rest };
+                        let path = track.picture(album, &hsmusic_media, art)?;
+                        report.art = Some(path.clone());
+
+                        let mut picture = Picture::from_reader(
+                            &mut File::open(path).context("failed to open picture")?,
+                        )
+                        .context("failed to create Picture")?;
+                        picture.set_pic_type(PictureType::CoverFront);
+
+                        tag.remove_picture_type(PictureType::CoverFront);
+                        tag.push_picture(picture);
                     }
+                }
+
+                if let Some(style) = &edits.add_artists {
+                    if let Some(artists) = &track.artists {
+                        let artists: Vec<_> = artists.iter().map(|x| x.who).collect();
 
-                    if edits.add_art.is_some() {
-                        if let Some(ArtTypes { first, rest }) = edits.add_art {
-                            let track_num = if edits.add_album {
-                                track.track_num
-                            } else {
-                                track_num
-                            };
-                            let art = if track_num <= 1 { first } else { rest };
-                            let path = track.picture(album, &hsmusic_media, art)?;
-
-                            let mut picture = Picture::from_reader(
-                                &mut File::open(path).context("failed to open picture")?,
-                            )
-                            .context("failed to create Picture")?;
-                            picture.set_pic_type(PictureType::CoverFront);
-
-                            tag.remove_picture_type(PictureType::CoverFront);
-                            tag.push_picture(picture);
+                        if verbose {
+                            eprintln!("artists: {}", artists.join(", "));
+                        }
+
+                        if let Some(key) = edits.profile.key(tag, Field::TrackArtist) {
+                            tags::write_artists(tag, key, &artists, style);
                         }
                     }
+                }
 
-                    if edits.add_artists {
-                        if let Some(artists) = &track.artists {
-                            let artists =
-                                artists.iter().map(|x| x.who).collect::<Vec<_>>().join(", ");
+                if edits.add_credits {
+                    tags::write_credits(tag, &edits.profile, track);
+                }
 
-                            if verbose {
-                                println!("artists: {}", artists);
-                            }
+                if edits.add_album {
+                    tags::write_album(
+                        tag,
+                        &edits.profile,
+                        album,
+                        track,
+                        edits.add_artists.as_ref(),
+                        edits.add_disc_numbers,
+                    );
+                }
+
+                if edits.add_dates {
+                    tags::write_dates(
+                        tag,
+                        &edits.profile,
+                        album.date,
+                        track.original_date,
+                        track.cover_art_date,
+                    );
+                }
+
+                if edits.add_ids {
+                    tags::write_ids(tag, &edits.profile, album, track);
+                }
 
-                            tag.insert_text(ItemKey::TrackArtist, artists);
+                if let Some(threshold) = edits.check_duration {
+                    let expected_duration = track.duration as u64;
+                    let difference = actual_duration.max(expected_duration)
+                        - actual_duration.min(expected_duration);
+
+                    if expected_duration > 0 && difference > threshold {
+                        report.warnings.push(format!(
+                            "file is {}s long but {:?} is {}s, it may be a bad match",
+                            actual_duration, track.name, expected_duration
+                        ));
+                    }
+                }
+
+                if edits.add_color && !track.color.is_empty() {
+                    tags::set_field(tag, &edits.profile, Field::Color, track.color.to_string());
+                }
+
+                if edits.add_references {
+                    tags::write_references(
+                        tag,
+                        &edits.profile,
+                        &references.references(track),
+                        references.referenced_by(track),
+                    );
+                }
+
+                if let Some(hosts) = &edits.add_urls {
+                    let artist_urls = track
+                        .artists
+                        .iter()
+                        .flatten()
+                        .next()
+                        .and_then(|x| artists.get(x.who))
+                        .map(|x| &x.urls[..])
+                        .unwrap_or_default();
+
+                    tags::write_urls(
+                        tag,
+                        &edits.profile,
+                        &track.urls,
+                        &album.urls,
+                        artist_urls,
+                        hosts,
+                    );
+                }
+
+                if let Some(mode) = edits.add_lyrics {
+                    if let Some(lyrics) = &track.lyrics {
+                        let lyrics = markup::to_plain_text(lyrics, &resolve_reference);
+
+                        if mode == LyricsMode::Embed {
+                            tags::set_field(tag, &edits.profile, Field::Lyrics, lyrics);
+                        } else {
+                            if !dry_run {
+                                report.outcome = report::Outcome::FailedWrite;
+                                write_lyrics_sidecar(&out_path, mode, album, track, &lyrics)?;
+                                report.outcome = report::Outcome::Failed;
+                            }
+                            sidecar = Some(mode);
                         }
                     }
+                }
+
+                if let Some(mapping) = &edits.add_genres {
+                    let genres = mapping.genres(album, track);
+                    if !genres.is_empty() {
+                        tags::set_multi_field(tag, &edits.profile, Field::Genre, genres);
+                    }
 
-                    if edits.add_album {
-                        tag.insert_text(ItemKey::AlbumTitle, album.name.to_string());
-                        tag.insert_text(ItemKey::TrackNumber, track.track_num.to_string());
-                        tag.insert_text(
-                            ItemKey::RecordingDate,
-                            album.date.format("%F").to_string(),
-                        );
+                    let groupings = mapping.groupings(album, track);
+                    if !groupings.is_empty() {
+                        tags::set_multi_field(tag, &edits.profile, Field::Grouping, groupings);
                     }
+                }
+
+                if edits.add_group_subtitle && album.uses_groups && !track.group.is_empty() {
+                    let group = track.group.to_string();
+                    tags::set_field(tag, &edits.profile, Field::GroupSubtitle, group);
+                }
+
+                if let Some(Commentary {
+                    source,
+                    field,
+                    max_len,
+                }) = edits.add_commentary
+                {
+                    let commentary = match source {
+                        CommentarySource::Track => vec![&track.commentary],
+                        CommentarySource::Album => vec![&album.commentary],
+                        CommentarySource::Both => vec![&track.commentary, &album.commentary],
+                    };
+                    let commentary: Vec<_> = commentary
+                        .into_iter()
+                        .flatten()
+                        .map(|x| markup::to_plain_text(x, &resolve_reference))
+                        .filter(|x| !x.is_empty())
+                        .collect();
+
+                    if !commentary.is_empty() {
+                        let mut commentary = commentary.join("\n\n");
+                        if let Some(max_len) = max_len {
+                            commentary = markup::truncate(&commentary, max_len);
+                        }
 
-                    metadata
-                        .save_to_path(&out_path)
-                        .context("failed to write metadata")?;
+                        let field = match field {
+                            CommentaryField::Comment => Field::Comment,
+                            CommentaryField::Custom => Field::Commentary,
+                        };
+                        tags::set_field(tag, &edits.profile, field, commentary);
+                    }
                 }
-            } else if verbose {
-                println!("not audio");
+
+                edits.merge.apply(&original, tag);
+
+                report.changes = report::changed_fields(&before, tag);
+                if let Some(mode) = sidecar {
+                    report.changes.push(format!("{:?} file", mode));
+                }
+
+                if !dry_run {
+                    report.outcome = report::Outcome::FailedWrite;
+                    tags::save_to_path(&metadata, &out_path).context("failed to write metadata")?;
+                }
+
+                report.outcome = report::Outcome::Matched;
+            } else if !has_tag {
+                report.outcome = report::Outcome::NoTag;
             }
+        } else {
+            report.outcome = report::Outcome::NotAudio;
+
+            if verbose {
+                eprintln!("not audio");
+            }
+        }
+
+        Ok(())
+    };
 
-            Ok(())
+    let files = entries
+        .into_par_iter()
+        .map(|entry| {
+            progress(entries_count);
+
+            let mut report = report::MatchReport {
+                path: entry.path().to_path_buf(),
+                ..Default::default()
+            };
+            if let Err(err) = process_file(&entry, &mut report) {
+                report.errors = err.chain().map(|x| x.to_string()).collect();
+            }
+            report
         })
-        .filter_map(|x: Result<(), anyhow::Error>| x.err())
         .collect();
 
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        let mut msgs = "Errors:\n".to_string();
-        for error in errors.drain(..5.min(errors.len())) {
-            writeln!(msgs, "* {:?}", error)?;
-        }
-        if !errors.is_empty() {
-            writeln!(msgs, "* ...and {} more", errors.len())?;
-        }
-        Err(anyhow!("{}", msgs))
-    }
+    Ok(report::RunReport {
+        warnings: data.warnings.clone(),
+        files,
+    })
 }
@@ -1,129 +1,232 @@
+use crate::catalog::{Catalog, Entry};
+use crate::matching::{self, Candidate, Query, Verdict};
+use crate::template::{PathInfo, PathTemplate};
 use crate::{bandcamp, hsmusic};
 use anyhow::{anyhow, Result};
-use itertools::Itertools;
+use std::cmp::Ordering;
+use std::path::Path;
 
-pub fn find_bandcamp_from_album_track<'a, 'b>(
-    album_name: &'a str,
-    title: &'a str,
-    track_num: usize,
-    albums: &'b [bandcamp::Album],
-) -> Result<Option<&'b bandcamp::Track>> {
-    if let Some(album) = albums.iter().find(|x| x.name == album_name) {
-        let track = album
-            .tracks
-            .iter()
-            .find(|x| x.name == title || x.name.splitn(2, " - ").last().unwrap_or("") == title)
-            .or_else(|| album.tracks.get(track_num - 1))
-            .ok_or_else(|| {
-                anyhow!(
-                    "found bandcamp album {:?} but not track {:?}",
-                    album_name,
-                    title
-                )
-            })?;
-        Ok(Some(track))
-    } else {
-        Ok(None)
-    }
+type Found<'c> = (&'c hsmusic::Album<'c>, &'c hsmusic::Track<'c>);
+
+/// A track, and how confident the fuzzy match with it is. Exact matches, like pins and Bandcamp
+/// URLs, don't have a confidence.
+type Scored<'c> = (Found<'c>, Option<f64>);
+
+fn found<'c>(entry: &Entry<'c>) -> Found<'c> {
+    (entry.album, entry.track)
 }
 
-fn find_hsmusic<'a>(
-    albums: &'a [hsmusic::Album<'a>],
-    mut f: impl FnMut(&'a hsmusic::Album, &'a hsmusic::Track) -> bool,
-) -> Option<(&'a hsmusic::Album, &'a hsmusic::Track)> {
-    albums
+/// Finds the best scoring track on the Bandcamp album named `album_name`, if there is such an
+/// album, along with how confident the match is. It's an error if no track is a confident match.
+pub fn find_bandcamp_from_album_track<'a, 'c>(
+    album_name: &'a str,
+    query: &Query<'a>,
+    min_confidence: f64,
+    catalog: &Catalog<'c>,
+) -> Result<Option<(&'c bandcamp::Track, f64)>> {
+    let album = match catalog.bandcamp_album(album_name) {
+        Some(album) => album,
+        None => return Ok(None),
+    };
+
+    let mut ranked: Vec<_> = album
+        .tracks
         .iter()
-        .flat_map(|album| album.tracks.iter().map(move |track| (album, track)))
-        .find(|(album, track)| f(album, track))
+        .map(|x| (x, query.bandcamp_confidence(x)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+    match matching::verdict(ranked.iter().map(|x| x.1), min_confidence) {
+        Verdict::Match => Ok(Some(ranked[0])),
+        Verdict::Ambiguous => Err(anyhow!(
+            "{} in bandcamp album {:?}",
+            matching::ambiguous_bandcamp(query.title, &ranked),
+            album.name
+        )),
+        Verdict::Unmatched => Err(anyhow!(
+            "found bandcamp album {:?} but not track {:?}",
+            album.name,
+            query.title
+        )),
+    }
 }
 
-pub fn find_hsmusic_from_bandcamp<'a, 'b>(
+pub fn find_hsmusic_from_bandcamp<'a, 'c>(
     bandcamp: &'a bandcamp::Track,
-    albums: &'b [hsmusic::Album<'b>],
-) -> Result<(&'b hsmusic::Album<'b>, &'b hsmusic::Track<'b>)> {
-    find_hsmusic(albums, |album, track| {
-        !matches!(
-            album.name,
-            "Homestuck Vol. 1" | "Homestuck Vol. 2" | "Homestuck Vol. 3" | "Homestuck Vol. 4"
-        ) && track.urls.iter().any(|&x| x == bandcamp.url)
-    })
-    .ok_or_else(|| anyhow!("couldn't find track {:?}", bandcamp.name))
+    catalog: &Catalog<'c>,
+) -> Result<Found<'c>> {
+    catalog
+        .from_url(&bandcamp.url)
+        .map(found)
+        .ok_or_else(|| anyhow!("couldn't find track {:?}", bandcamp.name))
 }
 
-fn special_hsmusic_from_album_track<'a, 'b, 'c>(
+/// The track pinned for this album and title in the overrides, if there is one.
+fn find_hsmusic_from_pin<'a, 'c>(
     album_name: &'a str,
     title: &'a str,
-    track_num: usize,
-    bandcamp_albums: &'b [bandcamp::Album],
-    hsmusic_albums: &'c [hsmusic::Album<'c>],
-) -> Result<Option<(&'c hsmusic::Album<'c>, &'c hsmusic::Track<'c>)>> {
-    match (album_name, title) {
-        ("Homestuck Vol. 9-10 (with [S] Collide. and Act 7)", "Frustracean") => {
-            Ok(find_hsmusic(hsmusic_albums, |_, track| {
-                track.name == "Frustracean"
-            }))
-        }
-        ("HIVESWAP: ACT 2 Original Soundtrack", title) => Ok(Some(find_hsmusic_from_album_track(
-            "Hiveswap Act 2 OST",
-            title,
-            track_num,
-            bandcamp_albums,
-            hsmusic_albums,
-        )?)),
-        _ => Ok(None),
-    }
+    catalog: &Catalog<'c>,
+) -> Result<Option<Found<'c>>> {
+    let pin = match catalog.overrides().pin(album_name, title) {
+        Some(pin) => pin,
+        None => return Ok(None),
+    };
+
+    let album = pin
+        .hsmusic_album
+        .as_deref()
+        .map(|name| {
+            catalog
+                .album(name)
+                .ok_or_else(|| anyhow!("couldn't find pinned album {:?}", name))
+        })
+        .transpose()?;
+    let pinned = catalog
+        .tracks_named(&pin.track)
+        .find(|x| match album {
+            Some(album) => x.album.directory == album.directory,
+            None => true,
+        })
+        .ok_or_else(|| anyhow!("couldn't find pinned track {:?}", pin.track))?;
+    Ok(Some(found(pinned)))
 }
 
-fn bandcamp_to_hsmusic_name(album: &str) -> &str {
-    match album {
-        "Homestuck - Strife!" => "Strife!",
-        other => other.trim_end_matches(" [UNOFFICIAL ALBUM]"),
-    }
+/// Finds the best scoring track in `album`. It's an error if no track is a confident match.
+fn find_hsmusic_in_album<'a, 'c>(
+    album: &hsmusic::Album,
+    query: &Query<'a>,
+    min_confidence: f64,
+    catalog: &Catalog<'c>,
+) -> Result<Candidate<'c>> {
+    let ranked = query.rank(catalog.album_tracks(album));
+    matching::best(query.title, &ranked, min_confidence)?
+        .ok_or_else(|| matching::ambiguous(query.title, &ranked))
 }
 
-pub fn find_hsmusic_from_album_track<'a, 'b, 'c>(
+/// Finds a tagged track: by its pin if it has one, or else the best scoring track of its album,
+/// going through Bandcamp for albums hsmusic names differently.
+pub fn find_hsmusic_from_album_track<'a, 'c>(
     album_name: &'a str,
-    title: &'a str,
-    track_num: usize,
-    bandcamp_albums: &'b [bandcamp::Album],
-    hsmusic_albums: &'c [hsmusic::Album<'c>],
-) -> Result<(&'c hsmusic::Album<'c>, &'c hsmusic::Track<'c>)> {
-    if let Some(special) = special_hsmusic_from_album_track(
-        album_name,
-        title,
-        track_num,
-        bandcamp_albums,
-        hsmusic_albums,
-    )? {
-        Ok(special)
-    } else if let Some(album) = hsmusic_albums
-        .iter()
-        .find(|x| x.name == bandcamp_to_hsmusic_name(album_name))
-    {
-        let track = &album
-            .tracks
-            .iter()
-            .find(|x| x.name == title)
-            .or_else(|| album.tracks.get(track_num - 1))
-            .ok_or_else(|| anyhow!("couldn't find track {:?} in album {:?}", title, album.name))?;
-        Ok((album, track))
+    query: &Query<'a>,
+    min_confidence: f64,
+    catalog: &Catalog<'c>,
+) -> Result<Scored<'c>> {
+    if let Some(pinned) = find_hsmusic_from_pin(album_name, query.title, catalog)? {
+        Ok((pinned, None))
+    } else if let Some(album) = catalog.album(catalog.overrides().album_name(album_name)) {
+        let found = find_hsmusic_in_album(album, query, min_confidence, catalog)
+            .map_err(|err| anyhow!("{} in album {:?}", err, album.name))?;
+        Ok(((found.album, found.track), Some(found.confidence)))
     } else {
-        let bandcamp =
-            find_bandcamp_from_album_track(album_name, title, track_num, bandcamp_albums)?
-                .ok_or_else(|| anyhow!("couldn't find track {:?}", title))?;
-        let hsmusic = find_hsmusic_from_bandcamp(bandcamp, hsmusic_albums)?;
-        Ok(hsmusic)
+        let (bandcamp, confidence) =
+            find_bandcamp_from_album_track(album_name, query, min_confidence, catalog)?
+                .ok_or_else(|| anyhow!("couldn't find track {:?}", query.title))?;
+        let hsmusic = find_hsmusic_from_bandcamp(bandcamp, catalog)?;
+        Ok((hsmusic, Some(confidence)))
     }
 }
 
-pub fn find_hsmusic_from_title<'a, 'b>(
-    title: &'a str,
-    hsmusic_albums: &'b [hsmusic::Album<'b>],
-) -> Option<(&'b hsmusic::Album<'b>, &'b hsmusic::Track<'b>)> {
-    hsmusic_albums
-        .iter()
-        .flat_map(|album| album.tracks.iter().map(move |track| (album, track)))
-        .filter(|(_album, track)| track.name == title)
-        .exactly_one()
-        .ok()
+pub fn find_hsmusic_from_ids<'a, 'c>(
+    album_directory: &'a str,
+    track_directory: &'a str,
+    catalog: &Catalog<'c>,
+) -> Option<Found<'c>> {
+    catalog
+        .from_ids(album_directory, track_directory)
+        .map(found)
+}
+
+/// Finds a track by title alone, in any album. `None` if nothing is a confident match.
+pub fn find_hsmusic_from_title<'a, 'c>(
+    query: &Query<'a>,
+    min_confidence: f64,
+    catalog: &Catalog<'c>,
+) -> Result<Option<Candidate<'c>>> {
+    let ranked = query.rank(catalog.tracks());
+    matching::best(query.title, &ranked, min_confidence)
+}
+
+/// Every track that could be a file's, best first: the tracks of its album if that's known, or
+/// else every track.
+pub fn candidates<'a, 'c>(
+    album_name: Option<&'a str>,
+    query: &Query<'a>,
+    catalog: &Catalog<'c>,
+) -> Vec<Candidate<'c>> {
+    match album_name.and_then(|x| catalog.album(catalog.overrides().album_name(x))) {
+        Some(album) => query.rank(catalog.album_tracks(album)),
+        None => query.rank(catalog.tracks()),
+    }
+}
+
+/// Finds the hsmusic track for an untagged file from its path, using the first template that
+/// fits and finds a track, along with what that template got out of the path. If none do, it's
+/// the first error from looking up an album and track number, if there was one.
+pub fn find_hsmusic_from_path<'a, 'c>(
+    rel_path: &'a Path,
+    templates: &'a [PathTemplate],
+    duration: Option<u64>,
+    min_confidence: f64,
+    catalog: &Catalog<'c>,
+) -> Result<Option<(PathInfo, Scored<'c>)>> {
+    let mut error = None;
+    for info in templates.iter().filter_map(|x| x.parse(rel_path)) {
+        match &info {
+            PathInfo {
+                album: Some(album),
+                track_num: Some(track_num),
+                title,
+                ..
+            } if *track_num > 0 => {
+                let query = Query {
+                    title: title.as_deref().unwrap_or(""),
+                    track_num: Some(*track_num),
+                    duration,
+                };
+                match find_hsmusic_from_album_track(album, &query, min_confidence, catalog) {
+                    Ok(found) => return Ok(Some((info, found))),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    }
+                }
+            }
+            PathInfo {
+                album,
+                title: Some(title),
+                track_num,
+                ..
+            } => {
+                let query = Query {
+                    title,
+                    track_num: *track_num,
+                    duration,
+                };
+                let in_album = match album
+                    .as_deref()
+                    .and_then(|x| catalog.album(catalog.overrides().album_name(x)))
+                {
+                    Some(album) => {
+                        let ranked = query.rank(catalog.album_tracks(album));
+                        matching::best(query.title, &ranked, min_confidence)?
+                    }
+                    None => None,
+                };
+                let found = match in_album {
+                    Some(found) => Some(found),
+                    None => find_hsmusic_from_title(&query, min_confidence, catalog)?,
+                };
+
+                if let Some(found) = found {
+                    let scored = ((found.album, found.track), Some(found.confidence));
+                    return Ok(Some((info, scored)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match error {
+        Some(err) => Err(err),
+        None => Ok(None),
+    }
 }
@@ -1,10 +1,21 @@
 use anyhow::{anyhow, ensure, Context, Result};
 use clap::Parser;
-use hsmusicifier::{ArtType, ArtTypes, Edits};
+use hsmusicifier::{
+    hsmusic::LoadMode,
+    manual::ManualMatches,
+    matching,
+    merge::{Merge, MergePolicy},
+    profile::Profile,
+    report::{write_report, ReportFormat},
+    template::PathTemplate,
+    ArtType, ArtTypes, ArtistStyle, Commentary, CommentaryField, CommentarySource, Edits,
+    LyricsMode,
+};
 use iui::{controls::*, prelude::*};
 use nfd::Response;
 use std::cell::RefCell;
-use std::env::current_exe;
+use std::env::{current_exe, temp_dir};
+use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{
@@ -140,12 +151,260 @@ fn run(ui: UI, mut win: Window) -> Result<()> {
     let mut add_artists = Checkbox::new(&ui, "Add artists");
     add_artists.set_checked(&ui, true);
 
+    let mut artist_style = Combobox::new(&ui);
+    artist_style.append(&ui, "Joined");
+    artist_style.append(&ui, "Multiple values");
+    artist_style.append(&ui, "First feat. rest");
+    artist_style.set_selected(&ui, 0);
+
+    let mut artist_separator = Entry::new(&ui);
+    artist_separator.set_value(&ui, ", ");
+
+    let mut artist_style_chooser = LayoutGrid::new(&ui);
+    artist_style_chooser.append(
+        &ui,
+        Label::new(&ui, "Artists:"),
+        0,
+        1,
+        1,
+        1,
+        GridExpand::Neither,
+        GridAlignment::Start,
+        GridAlignment::Center,
+    );
+    artist_style_chooser.append(
+        &ui,
+        artist_style.clone(),
+        1,
+        1,
+        1,
+        1,
+        GridExpand::Both,
+        GridAlignment::End,
+        GridAlignment::Fill,
+    );
+    artist_style_chooser.set_padded(&ui, true);
+
+    let mut artist_separator_chooser = LayoutGrid::new(&ui);
+    artist_separator_chooser.append(
+        &ui,
+        Label::new(&ui, "Separator:"),
+        0,
+        1,
+        1,
+        1,
+        GridExpand::Neither,
+        GridAlignment::Start,
+        GridAlignment::Center,
+    );
+    artist_separator_chooser.append(
+        &ui,
+        artist_separator.clone(),
+        1,
+        1,
+        1,
+        1,
+        GridExpand::Both,
+        GridAlignment::End,
+        GridAlignment::Fill,
+    );
+    artist_separator_chooser.set_padded(&ui, true);
+
+    add_artists.on_toggled(&ui, {
+        let ui = ui.clone();
+        let mut artist_style = artist_style.clone();
+        let mut artist_separator = artist_separator.clone();
+        move |add_artists| {
+            if add_artists {
+                artist_style.enable(&ui);
+                artist_separator.enable(&ui);
+            } else {
+                artist_style.disable(&ui);
+                artist_separator.disable(&ui);
+            }
+        }
+    });
+
+    let mut add_credits = Checkbox::new(&ui, "Add credits");
+    add_credits.set_checked(&ui, false);
+
     let mut add_album = Checkbox::new(&ui, "Add album");
     add_album.set_checked(&ui, false);
 
+    let mut add_disc_numbers = Checkbox::new(&ui, "Number discs by album section");
+    add_disc_numbers.set_checked(&ui, false);
+    add_disc_numbers.disable(&ui);
+
+    add_album.on_toggled(&ui, {
+        let ui = ui.clone();
+        let mut add_disc_numbers = add_disc_numbers.clone();
+        move |add_album| {
+            if add_album {
+                add_disc_numbers.enable(&ui);
+            } else {
+                add_disc_numbers.disable(&ui);
+            }
+        }
+    });
+
+    let mut add_dates = Checkbox::new(&ui, "Add dates");
+    add_dates.set_checked(&ui, false);
+
+    let mut add_ids = Checkbox::new(&ui, "Remember matched tracks");
+    add_ids.set_checked(&ui, false);
+
+    let mut add_urls = Checkbox::new(&ui, "Add links");
+    add_urls.set_checked(&ui, false);
+
+    let mut add_color = Checkbox::new(&ui, "Add track color");
+    add_color.set_checked(&ui, false);
+
+    let mut check_duration = Checkbox::new(&ui, "Warn about length mismatches");
+    check_duration.set_checked(&ui, false);
+
+    let mut add_references = Checkbox::new(&ui, "Add references");
+    add_references.set_checked(&ui, false);
+
+    let mut add_group_subtitle = Checkbox::new(&ui, "Add album section as disc subtitle");
+    add_group_subtitle.set_checked(&ui, false);
+
+    let mut lyrics = Combobox::new(&ui);
+    lyrics.append(&ui, "Don't add");
+    lyrics.append(&ui, "Embed in file");
+    lyrics.append(&ui, ".lrc file");
+    lyrics.append(&ui, ".txt file");
+    lyrics.set_selected(&ui, 0);
+
+    let mut lyrics_chooser = LayoutGrid::new(&ui);
+    lyrics_chooser.append(
+        &ui,
+        Label::new(&ui, "Lyrics:"),
+        0,
+        1,
+        1,
+        1,
+        GridExpand::Neither,
+        GridAlignment::Start,
+        GridAlignment::Center,
+    );
+    lyrics_chooser.append(
+        &ui,
+        lyrics.clone(),
+        1,
+        1,
+        1,
+        1,
+        GridExpand::Both,
+        GridAlignment::End,
+        GridAlignment::Fill,
+    );
+    lyrics_chooser.set_padded(&ui, true);
+
+    let mut commentary = Combobox::new(&ui);
+    commentary.append(&ui, "Don't add");
+    commentary.append(&ui, "Track");
+    commentary.append(&ui, "Album");
+    commentary.append(&ui, "Track and album");
+    commentary.set_selected(&ui, 0);
+
+    let mut commentary_chooser = LayoutGrid::new(&ui);
+    commentary_chooser.append(
+        &ui,
+        Label::new(&ui, "Commentary:"),
+        0,
+        1,
+        1,
+        1,
+        GridExpand::Neither,
+        GridAlignment::Start,
+        GridAlignment::Center,
+    );
+    commentary_chooser.append(
+        &ui,
+        commentary.clone(),
+        1,
+        1,
+        1,
+        1,
+        GridExpand::Both,
+        GridAlignment::End,
+        GridAlignment::Fill,
+    );
+    commentary_chooser.set_padded(&ui, true);
+
+    let mut profile = Combobox::new(&ui);
+    profile.append(&ui, "Any");
+    profile.append(&ui, "foobar2000");
+    profile.append(&ui, "MusicBee");
+    profile.append(&ui, "Navidrome");
+    profile.append(&ui, "iTunes");
+    profile.set_selected(&ui, 0);
+
+    let mut profile_chooser = LayoutGrid::new(&ui);
+    profile_chooser.append(
+        &ui,
+        Label::new(&ui, "Player:"),
+        0,
+        1,
+        1,
+        1,
+        GridExpand::Neither,
+        GridAlignment::Start,
+        GridAlignment::Center,
+    );
+    profile_chooser.append(
+        &ui,
+        profile.clone(),
+        1,
+        1,
+        1,
+        1,
+        GridExpand::Both,
+        GridAlignment::End,
+        GridAlignment::Fill,
+    );
+    profile_chooser.set_padded(&ui, true);
+
     let mut add_art = Checkbox::new(&ui, "Add art");
     add_art.set_checked(&ui, true);
 
+    let mut path_templates = Entry::new(&ui);
+    path_templates.set_value(&ui, "{artist} - {title}");
+
+    let mut path_templates_chooser = LayoutGrid::new(&ui);
+    path_templates_chooser.append(
+        &ui,
+        Label::new(&ui, "Untagged files (;-separated):"),
+        0,
+        1,
+        1,
+        1,
+        GridExpand::Neither,
+        GridAlignment::Start,
+        GridAlignment::Center,
+    );
+    path_templates_chooser.append(
+        &ui,
+        path_templates.clone(),
+        1,
+        1,
+        1,
+        1,
+        GridExpand::Both,
+        GridAlignment::End,
+        GridAlignment::Fill,
+    );
+    path_templates_chooser.set_padded(&ui, true);
+
+    let mut fill_empty = Checkbox::new(&ui, "Only fill in missing tags");
+    fill_empty.set_checked(&ui, false);
+
+    let mut backup_original = Checkbox::new(&ui, "Back up replaced tags");
+    backup_original.set_checked(&ui, false);
+
+    let mut lenient = Checkbox::new(&ui, "Skip broken hsmusic data");
+    lenient.set_checked(&ui, true);
+
     let mut first_art = Combobox::new(&ui);
     first_art.append(&ui, "Album Art");
     first_art.append(&ui, "Track Art");
@@ -229,21 +488,72 @@ fn run(ui: UI, mut win: Window) -> Result<()> {
         let progress = progress.clone();
         let progress_total = progress_total.clone();
         let add_artists = add_artists.clone();
+        let add_credits = add_credits.clone();
+        let artist_style = artist_style;
+        let artist_separator = artist_separator;
         let add_album = add_album.clone();
+        let add_disc_numbers = add_disc_numbers.clone();
+        let add_group_subtitle = add_group_subtitle.clone();
+        let add_dates = add_dates.clone();
+        let add_ids = add_ids.clone();
+        let add_urls = add_urls.clone();
+        let add_color = add_color.clone();
+        let check_duration = check_duration.clone();
+        let add_references = add_references.clone();
+        let lyrics = lyrics;
+        let commentary = commentary;
+        let profile = profile;
         let add_art = add_art.clone();
+        let lenient = lenient.clone();
+        let fill_empty = fill_empty.clone();
+        let backup_original = backup_original.clone();
+        let path_templates = path_templates;
         let first_art = first_art;
         let rest_art = rest_art;
         move |_| {
             let input_path = PathBuf::from(&input_entry.value(&ui));
             let output_path = PathBuf::from(&output_entry.value(&ui));
 
+            let path_templates = match path_templates
+                .value(&ui)
+                .split(';')
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<PathTemplate>>>()
+            {
+                Ok(path_templates) => path_templates,
+                Err(err) => {
+                    win.modal_err(&ui, "Error", &err.to_string());
+                    return;
+                }
+            };
+
+            let manual_matches =
+                match ManualMatches::read(ManualMatches::path_beside(&bandcamp_json)) {
+                    Ok(manual_matches) => manual_matches,
+                    Err(err) => {
+                        win.modal_err(&ui, "Error", &err.to_string());
+                        return;
+                    }
+                };
+
             win.set_child(&ui, add.clone());
 
             let progress = progress.clone();
             let progress_total = progress_total.clone();
 
             let edits = Edits {
-                add_artists: add_artists.checked(&ui),
+                add_artists: if add_artists.checked(&ui) {
+                    Some(match artist_style.selected(&ui) {
+                        0 => ArtistStyle::Joined(artist_separator.value(&ui)),
+                        1 => ArtistStyle::Multiple,
+                        _ => ArtistStyle::Featuring,
+                    })
+                } else {
+                    None
+                },
+                add_credits: add_credits.checked(&ui),
                 add_art: if add_art.checked(&ui) {
                     Some(ArtTypes {
                         first: if first_art.selected(&ui) == 0 {
@@ -261,45 +571,143 @@ fn run(ui: UI, mut win: Window) -> Result<()> {
                     None
                 },
                 add_album: add_album.checked(&ui),
+                add_disc_numbers: add_disc_numbers.checked(&ui),
+                add_lyrics: match lyrics.selected(&ui) {
+                    1 => Some(LyricsMode::Embed),
+                    2 => Some(LyricsMode::Lrc),
+                    3 => Some(LyricsMode::Txt),
+                    _ => None,
+                },
+                add_commentary: match commentary.selected(&ui) {
+                    1 => Some(CommentarySource::Track),
+                    2 => Some(CommentarySource::Album),
+                    3 => Some(CommentarySource::Both),
+                    _ => None,
+                }
+                .map(|source| Commentary {
+                    source,
+                    field: CommentaryField::Comment,
+                    max_len: None,
+                }),
+                add_genres: None,
+                add_group_subtitle: add_group_subtitle.checked(&ui),
+                add_color: add_color.checked(&ui),
+                add_references: add_references.checked(&ui),
+                check_duration: if check_duration.checked(&ui) {
+                    Some(5)
+                } else {
+                    None
+                },
+                add_dates: add_dates.checked(&ui),
+                add_ids: add_ids.checked(&ui),
+                add_urls: if add_urls.checked(&ui) {
+                    Some(vec![])
+                } else {
+                    None
+                },
+                profile: match profile.selected(&ui) {
+                    1 => Profile::builtin("foobar2000"),
+                    2 => Profile::builtin("musicbee"),
+                    3 => Profile::builtin("navidrome"),
+                    4 => Profile::builtin("itunes"),
+                    _ => None,
+                }
+                .unwrap_or_default(),
+                merge: Merge::new(
+                    if fill_empty.checked(&ui) {
+                        MergePolicy::FillEmpty
+                    } else {
+                        MergePolicy::Overwrite
+                    },
+                    vec![],
+                    backup_original.checked(&ui),
+                ),
+                path_templates,
+                min_confidence: matching::DEFAULT_MIN_CONFIDENCE,
+                manual_matches,
+            };
+
+            let load_mode = if lenient.checked(&ui) {
+                LoadMode::Lenient
+            } else {
+                LoadMode::Strict
             };
 
             let hsmusic_data = hsmusic_data.clone();
             let hsmusic_media = hsmusic_media.clone();
             let bandcamp_json = bandcamp_json.clone();
             let tx = tx.clone();
-            thread.replace(Some(thread::spawn(
-                move || match std::panic::catch_unwind(|| {
-                    hsmusicifier::add_art(
+            let report_path = output_path.join("hsmusicifier-report.csv");
+            thread.replace(Some(thread::spawn(move || match std::panic::catch_unwind(
+                || -> Result<Option<String>> {
+                    let run_report = hsmusicifier::add_art(
                         bandcamp_json,
                         hsmusic_data,
                         hsmusic_media,
+                        load_mode,
+                        Some(temp_dir().join("hsmusicifier-cache.bin")),
                         edits,
                         true,
+                        false,
                         input_path,
                         output_path,
                         |total| {
                             progress_total.store(total, Ordering::SeqCst);
                             progress.fetch_add(1, Ordering::SeqCst);
                         },
-                    )
-                }) {
-                    Ok(Ok(())) => tx.send(Ok(())).unwrap(),
-                    Ok(Err(err)) => tx.send(Err(err)).unwrap(),
-                    Err(panic) => {
-                        let msg = match panic.downcast_ref::<&'static str>() {
-                            Some(s) => *s,
-                            None => match panic.downcast_ref::<String>() {
-                                Some(s) => &s[..],
-                                None => "Box<Any>",
-                            },
-                        };
-
-                        tx.send(Err(anyhow!("panicked at '{}'", msg))).unwrap();
-
-                        std::panic::resume_unwind(panic);
+                    )?;
+
+                    let files_went_wrong = run_report
+                        .files
+                        .iter()
+                        .any(|x| !x.errors.is_empty() || !x.warnings.is_empty());
+                    if !files_went_wrong && run_report.warnings.is_empty() {
+                        return Ok(None);
                     }
+
+                    let mut message = run_report.summary();
+                    if !run_report.warnings.is_empty() {
+                        message.push_str("\n\nSkipped broken hsmusic-data:");
+                        for warning in run_report.warnings.iter().take(10) {
+                            message.push_str(&format!("\n{}", warning));
+                        }
+                        if run_report.warnings.len() > 10 {
+                            message.push_str(&format!(
+                                "\n...and {} more",
+                                run_report.warnings.len() - 10
+                            ));
+                        }
+                    }
+
+                    if files_went_wrong {
+                        let file = File::create(&report_path).with_context(|| {
+                            format!("failed to create report {}", report_path.display())
+                        })?;
+                        write_report(&run_report.files, ReportFormat::Csv, file)?;
+                        message.push_str(&format!(
+                            "\n\nSee {} for every file, with its errors and warnings.",
+                            report_path.display()
+                        ));
+                    }
+
+                    Ok(Some(message))
                 },
-            )));
+            ) {
+                Ok(res) => tx.send(res).unwrap(),
+                Err(panic) => {
+                    let msg = match panic.downcast_ref::<&'static str>() {
+                        Some(s) => *s,
+                        None => match panic.downcast_ref::<String>() {
+                            Some(s) => &s[..],
+                            None => "Box<Any>",
+                        },
+                    };
+
+                    tx.send(Err(anyhow!("panicked at '{}'", msg))).unwrap();
+
+                    std::panic::resume_unwind(panic);
+                }
+            })));
         }
     });
 
@@ -318,10 +726,29 @@ fn run(ui: UI, mut win: Window) -> Result<()> {
     select.append(&ui, output_chooser, LayoutStrategy::Compact);
     select.append(&ui, HorizontalSeparator::new(&ui), LayoutStrategy::Compact);
     select.append(&ui, add_artists, LayoutStrategy::Compact);
+    select.append(&ui, artist_style_chooser, LayoutStrategy::Compact);
+    select.append(&ui, artist_separator_chooser, LayoutStrategy::Compact);
+    select.append(&ui, add_credits, LayoutStrategy::Compact);
     select.append(&ui, add_album, LayoutStrategy::Compact);
+    select.append(&ui, add_disc_numbers, LayoutStrategy::Compact);
+    select.append(&ui, add_dates, LayoutStrategy::Compact);
+    select.append(&ui, add_ids, LayoutStrategy::Compact);
+    select.append(&ui, add_urls, LayoutStrategy::Compact);
+    select.append(&ui, add_color, LayoutStrategy::Compact);
+    select.append(&ui, check_duration, LayoutStrategy::Compact);
+    select.append(&ui, add_references, LayoutStrategy::Compact);
+    select.append(&ui, add_group_subtitle, LayoutStrategy::Compact);
+    select.append(&ui, lyrics_chooser, LayoutStrategy::Compact);
+    select.append(&ui, commentary_chooser, LayoutStrategy::Compact);
     select.append(&ui, add_art, LayoutStrategy::Compact);
     select.append(&ui, first_art_chooser, LayoutStrategy::Compact);
     select.append(&ui, rest_art_chooser, LayoutStrategy::Compact);
+    select.append(&ui, HorizontalSeparator::new(&ui), LayoutStrategy::Compact);
+    select.append(&ui, profile_chooser, LayoutStrategy::Compact);
+    select.append(&ui, path_templates_chooser, LayoutStrategy::Compact);
+    select.append(&ui, fill_empty, LayoutStrategy::Compact);
+    select.append(&ui, backup_original, LayoutStrategy::Compact);
+    select.append(&ui, lenient, LayoutStrategy::Compact);
     select.append(&ui, Spacer::new(&ui), LayoutStrategy::Stretchy);
     select.append(&ui, next_button, LayoutStrategy::Compact);
 
@@ -381,9 +808,12 @@ fn run(ui: UI, mut win: Window) -> Result<()> {
             }
 
             match rx.try_recv() {
-                Ok(Ok(())) => {
+                Ok(Ok(problems)) => {
                     thread.borrow_mut().take().unwrap().join().unwrap();
                     win.set_child(&ui, finish.clone());
+                    if let Some(problems) = problems {
+                        win.modal_msg(&ui, "Some things went wrong", &problems);
+                    }
                 }
                 Ok(Err(err)) => {
                     win.modal_err(&ui, "Error", &err.to_string());
@@ -0,0 +1,99 @@
+//! manual matches, for files the matcher gets wrong
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{read, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+const HASH_PREFIX: &str = "sha256:";
+
+/// Files and the hsmusic track each of them is, as `album-directory/track-directory`. Files are
+/// either their path relative to the input directory, or `sha256:` and a hash of their contents
+/// so the match survives renames:
+///
+/// ```yaml
+/// "Homestuck Vol. 5/01 Descend.mp3": homestuck-vol-5/descend
+/// "sha256:9f86d081884c7d65...": strife/strife
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ManualMatches {
+    matches: BTreeMap<String, String>,
+}
+
+/// `rel_path` with `/` separators, so match files work across platforms.
+pub fn path_key(rel_path: &Path) -> Option<String> {
+    Some(
+        rel_path
+            .iter()
+            .map(|x| x.to_str())
+            .collect::<Option<Vec<_>>>()?
+            .join("/"),
+    )
+}
+
+/// A key for the contents of the file at `path`.
+pub fn hash_key(path: &Path) -> Result<String> {
+    let bytes = read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let hash = Sha256::digest(&bytes);
+    let hex = hash
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<String>();
+    Ok(format!("{}{}", HASH_PREFIX, hex))
+}
+
+impl ManualMatches {
+    /// Where the manual matches for `bandcamp_json` are by default: `matches.yaml` in the same
+    /// directory.
+    pub fn path_beside(bandcamp_json: &Path) -> PathBuf {
+        bandcamp_json.with_file_name("matches.yaml")
+    }
+
+    /// Reads manual matches from a YAML or JSON file. A missing file has no matches.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let string = read_to_string(path)
+            .with_context(|| format!("failed to read manual matches {}", path.display()))?;
+        let matches = serde_yaml::from_str::<Option<_>>(&string)
+            .with_context(|| format!("failed to parse manual matches {}", path.display()))?;
+        Ok(Self {
+            matches: matches.unwrap_or_default(),
+        })
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        write(path, serde_yaml::to_string(&self.matches)?)
+            .with_context(|| format!("failed to write manual matches {}", path.display()))
+    }
+
+    pub fn insert(&mut self, key: String, album_directory: &str, track_directory: &str) {
+        self.matches
+            .insert(key, format!("{}/{}", album_directory, track_directory));
+    }
+
+    /// The album and track directories for the file at `in_path`, found at `rel_path` in the
+    /// input directory. Files are only hashed if some match is by hash.
+    pub fn lookup(&self, rel_path: &Path, in_path: &Path) -> Result<Option<(&str, &str)>> {
+        let mut found = path_key(rel_path).and_then(|x| self.matches.get(&x));
+        if found.is_none() && self.matches.keys().any(|x| x.starts_with(HASH_PREFIX)) {
+            found = self.matches.get(&hash_key(in_path)?);
+        }
+
+        found
+            .map(|x| {
+                x.split_once('/').ok_or_else(|| {
+                    anyhow!(
+                        "manual match {} for {} isn't album/track",
+                        x,
+                        rel_path.display()
+                    )
+                })
+            })
+            .transpose()
+    }
+}
@@ -0,0 +1,48 @@
+//! converting hsmusic's wiki markup and HTML into plain text
+use htmlescape::decode_html;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+static WIKI_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[\[(?:([\w-]+):)?([^\]|]+)(?:\|([^\]]+))?\]\]").unwrap());
+static MARKDOWN_LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[([^\]]+)\]\([^)]+\)").unwrap());
+static LINE_BREAK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<br\s*/?>").unwrap());
+static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]+>").unwrap());
+static BLANK_LINES: Lazy<Regex> = Lazy::new(|| Regex::new(r"\n{3,}").unwrap());
+
+/// Converts markup to plain text. `[[kind:reference]]` links without a label are passed to
+/// `resolve` (e.g. `("track", "showtime")`) to get a readable name.
+pub fn to_plain_text(markup: &str, resolve: &dyn Fn(&str, &str) -> Option<String>) -> String {
+    let text = WIKI_LINK.replace_all(markup, |caps: &Captures| {
+        if let Some(label) = caps.get(3) {
+            return label.as_str().to_string();
+        }
+
+        let reference = &caps[2];
+        match caps.get(1) {
+            Some(kind) => {
+                resolve(kind.as_str(), reference).unwrap_or_else(|| reference.to_string())
+            }
+            None => reference.to_string(),
+        }
+    });
+    let text = MARKDOWN_LINK.replace_all(&text, "$1");
+    let text = LINE_BREAK.replace_all(&text, "\n");
+    let text = TAG.replace_all(&text, "");
+    let text = decode_html(&text).unwrap_or_else(|_| text.to_string());
+    let text = BLANK_LINES.replace_all(&text, "\n\n");
+
+    text.trim().to_string()
+}
+
+/// Cuts `text` down to at most `max_len` characters, marking the cut with an ellipsis.
+pub fn truncate(text: &str, max_len: usize) -> String {
+    if text.chars().count() <= max_len {
+        return text.to_string();
+    }
+
+    let mut truncated: String = text.chars().take(max_len.saturating_sub(1)).collect();
+    truncated.truncate(truncated.trim_end().len());
+    truncated.push('…');
+    truncated
+}
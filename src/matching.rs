@@ -0,0 +1,426 @@
+//! fuzzy, scored matching of tagged titles against hsmusic tracks
+use crate::bandcamp;
+use crate::catalog::Entry;
+use crate::hsmusic::{Album, Track};
+use anyhow::{anyhow, ensure, Error, Result};
+use htmlescape::decode_html;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::cmp::Ordering;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// The confidence a match needs by default.
+pub const DEFAULT_MIN_CONFIDENCE: f64 = 0.65;
+
+/// Parses a confidence, from 0 to 1.
+pub fn parse_confidence(s: &str) -> Result<f64> {
+    let confidence: f64 = s.parse()?;
+    ensure!(
+        (0.0..=1.0).contains(&confidence),
+        "Bad confidence {}, it should be between 0 and 1!",
+        s
+    );
+    Ok(confidence)
+}
+
+/// How close the runner-up can be to the best match before it's ambiguous which was meant.
+const AMBIGUITY_MARGIN: f64 = 0.05;
+
+const TITLE_WEIGHT: f64 = 0.6;
+const TRACK_NUM_WEIGHT: f64 = 0.25;
+const DURATION_WEIGHT: f64 = 0.15;
+
+/// A length difference of this many seconds or more counts as not matching at all.
+const DURATION_TOLERANCE: f64 = 10.0;
+
+static SUFFIX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\s*(?:\(([^()]*)\)|\[([^\[\]]*)\]|-\s+([^-]*))\s*$").unwrap());
+
+/// Whether a trailing `(...)`, `[...]` or `- ...` doesn't make a different track.
+fn is_ignored_suffix(suffix: &str) -> bool {
+    let suffix = suffix.trim();
+    suffix.contains("remaster")
+        || matches!(
+            suffix,
+            "bonus" | "bonus track" | "explicit" | "album version" | "official audio"
+        )
+}
+
+/// Normalizes a name for comparison: HTML entities are decoded, accents, case, quotes and
+/// punctuation are dropped, `&` becomes `and`, and suffixes like `(Remastered)` are stripped.
+pub fn normalize(name: &str) -> String {
+    let name = decode_html(name).unwrap_or_else(|_| name.to_string());
+    let name: String = name
+        .nfkd()
+        .filter(|&x| !is_combining_mark(x))
+        .flat_map(char::to_lowercase)
+        .filter(|x| !matches!(x, '\'' | '‘' | '’' | '‛' | '`' | '´'))
+        .collect();
+    let mut name = name.replace('&', " and ");
+
+    while let Some(captures) = SUFFIX.captures(&name) {
+        let suffix = captures.iter().skip(1).flatten().next().map(|x| x.as_str());
+        match (captures.get(0), suffix) {
+            (Some(all), Some(suffix)) if all.start() > 0 && is_ignored_suffix(suffix) => {
+                name.truncate(all.start());
+            }
+            _ => break,
+        }
+    }
+
+    name.split(|x: char| !x.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What's known about the file being matched.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Query<'a> {
+    pub title: &'a str,
+    pub track_num: Option<usize>,
+    /// The file's length, in seconds
+    pub duration: Option<u64>,
+}
+
+/// A possible match, and how confident we are in it, from 0 to 1.
+#[derive(Copy, Clone, Debug)]
+pub struct Candidate<'c> {
+    pub album: &'c Album<'c>,
+    pub track: &'c Track<'c>,
+    pub confidence: f64,
+}
+
+impl Query<'_> {
+    /// Scores a track by its title, track number and length, leaving out whatever either side
+    /// doesn't know. `title` and `name` are the query's title and the track's name, normalized,
+    /// and a track number or length of 0 is unknown.
+    fn score(&self, title: &str, name: &str, track_num: usize, duration: usize) -> f64 {
+        let mut total = 0.0;
+        let mut weights = 0.0;
+
+        if !title.is_empty() {
+            total += TITLE_WEIGHT * strsim::normalized_levenshtein(title, name);
+            weights += TITLE_WEIGHT;
+        }
+
+        if let Some(query_num) = self.track_num.filter(|&x| x > 0 && track_num > 0) {
+            total += TRACK_NUM_WEIGHT * (query_num == track_num) as u8 as f64;
+            weights += TRACK_NUM_WEIGHT;
+        }
+
+        if let Some(query_duration) = self.duration.filter(|&x| x > 0 && duration > 0) {
+            let difference = (query_duration as f64 - duration as f64).abs();
+            total += DURATION_WEIGHT * (1.0 - difference / DURATION_TOLERANCE).max(0.0);
+            weights += DURATION_WEIGHT;
+        }
+
+        if weights > 0.0 {
+            total / weights
+        } else {
+            0.0
+        }
+    }
+
+    /// How confident a match with `track` is.
+    pub fn confidence(&self, track: &Track) -> f64 {
+        self.score(
+            &normalize(self.title),
+            &normalize(&track.name),
+            track.track_num,
+            track.duration,
+        )
+    }
+
+    /// How confident a match with a Bandcamp track is, by its name with or without an
+    /// `Artist - ` prefix, and its position.
+    pub fn bandcamp_confidence(&self, track: &bandcamp::Track) -> f64 {
+        let title = normalize(self.title);
+        let names = [
+            &track.name[..],
+            track.name.splitn(2, " - ").last().unwrap_or(""),
+        ];
+        names
+            .iter()
+            .map(|x| self.score(&title, &normalize(x), track.num, 0))
+            .fold(0.0, f64::max)
+    }
+
+    /// Scores every entry, best first.
+    pub fn rank<'e, 'c: 'e>(
+        &self,
+        entries: impl IntoIterator<Item = &'e Entry<'c>>,
+    ) -> Vec<Candidate<'c>> {
+        let title = normalize(self.title);
+        let mut candidates = entries
+            .into_iter()
+            .map(|entry| Candidate {
+                album: entry.album,
+                track: entry.track,
+                confidence: self.score(
+                    &title,
+                    &entry.name,
+                    entry.track.track_num,
+                    entry.track.duration,
+                ),
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| {
+            b.confidence
+                .partial_cmp(&a.confidence)
+                .unwrap_or(Ordering::Equal)
+        });
+        candidates
+    }
+}
+
+/// An error listing the best few candidates, for when none of them is clearly the match.
+fn ambiguous_among(title: &str, candidates: impl IntoIterator<Item = String>) -> Error {
+    let candidates = candidates.into_iter().take(3).collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return anyhow!("couldn't find track {:?}", title);
+    }
+
+    anyhow!(
+        "track {:?} is ambiguous, best candidates are {}",
+        title,
+        candidates.join(", ")
+    )
+}
+
+/// An error listing the best few of `ranked`, for when none of them is clearly the match.
+pub fn ambiguous(title: &str, ranked: &[Candidate]) -> Error {
+    ambiguous_among(
+        title,
+        ranked.iter().map(|x| {
+            format!(
+                "{:?} in {:?} ({:.0}%)",
+                x.track.name,
+                x.album.name,
+                x.confidence * 100.0
+            )
+        }),
+    )
+}
+
+/// An error listing the best few of the ranked tracks of a Bandcamp album.
+pub fn ambiguous_bandcamp(title: &str, ranked: &[(&bandcamp::Track, f64)]) -> Error {
+    ambiguous_among(
+        title,
+        ranked
+            .iter()
+            .map(|(track, confidence)| format!("{:?} ({:.0}%)", track.name, confidence * 100.0)),
+    )
+}
+
+/// How the best of some ranked candidates stands.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The best is the match
+    Match,
+    /// The runner-up is too close to tell apart
+    Ambiguous,
+    /// Nothing reaches the minimum confidence
+    Unmatched,
+}
+
+/// Judges `confidences`, sorted best first, against `min_confidence`.
+pub fn verdict(confidences: impl IntoIterator<Item = f64>, min_confidence: f64) -> Verdict {
+    let mut confidences = confidences.into_iter();
+    match (confidences.next(), confidences.next()) {
+        (Some(first), _) if first < min_confidence => Verdict::Unmatched,
+        (Some(first), Some(second)) if first - second < AMBIGUITY_MARGIN => Verdict::Ambiguous,
+        (Some(_), _) => Verdict::Match,
+        (None, _) => Verdict::Unmatched,
+    }
+}
+
+/// Picks the best of `ranked`, or `None` if nothing reaches `min_confidence`. It's an error if
+/// the runner-up is too close to tell apart.
+pub fn best<'c>(
+    title: &str,
+    ranked: &[Candidate<'c>],
+    min_confidence: f64,
+) -> Result<Option<Candidate<'c>>> {
+    match verdict(ranked.iter().map(|x| x.confidence), min_confidence) {
+        Verdict::Match => Ok(Some(ranked[0])),
+        Verdict::Ambiguous => Err(ambiguous(title, ranked)),
+        Verdict::Unmatched => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    fn track(name: &str, track_num: usize, duration: usize) -> Track<'static> {
+        Track {
+            name: name.to_string(),
+            commentary: None,
+            lyrics: None,
+            original_date: None,
+            cover_art_date: "2009-01-01".parse().unwrap(),
+            references: vec![],
+            artists: None,
+            cover_artists: None,
+            art_tags: vec![],
+            contributors: vec![],
+            directory: Cow::Owned(normalize(name).replace(' ', "-")),
+            aka: None,
+            duration,
+            urls: vec![],
+            group: "",
+            color: "",
+            track_num,
+        }
+    }
+
+    fn album(tracks: Vec<Track<'static>>) -> Album<'static> {
+        let date = "2009-01-01".parse().unwrap();
+        Album {
+            name: "Homestuck Vol. 5",
+            artists: None,
+            date,
+            track_art_date: date,
+            cover_art_date: date,
+            cover_artists: None,
+            has_track_art: false,
+            track_cover_artists: None,
+            art_tags: vec![],
+            commentary: None,
+            urls: vec![],
+            groups: vec![],
+            directory: Cow::Borrowed("homestuck-vol-5"),
+            is_major_release: true,
+            color: "",
+            uses_groups: false,
+            tracks,
+        }
+    }
+
+    fn entries<'c>(album: &'c Album<'c>) -> Vec<Entry<'c>> {
+        album
+            .tracks
+            .iter()
+            .map(|track| Entry {
+                album,
+                track,
+                name: normalize(&track.name),
+            })
+            .collect()
+    }
+
+    fn best_name(query: &Query, album: &Album) -> Result<Option<String>> {
+        let entries = entries(album);
+        let ranked = query.rank(&entries);
+        Ok(best(query.title, &ranked, DEFAULT_MIN_CONFIDENCE)?.map(|x| x.track.name.clone()))
+    }
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize("Descend"), "descend");
+        assert_eq!(normalize("Café &amp; Bar"), "cafe and bar");
+        assert_eq!(normalize("Jade&rsquo;s Lullaby"), "jades lullaby");
+        assert_eq!(normalize("Doctor (Deep Breath)"), "doctor deep breath");
+        assert_eq!(normalize("Showtime (Remastered 2015)"), "showtime");
+        assert_eq!(normalize("Showtime [Bonus Track] (Explicit)"), "showtime");
+        assert_eq!(
+            normalize("Sburban Jungle - Album Version"),
+            "sburban jungle"
+        );
+        assert_eq!(normalize("(Remastered)"), "remastered");
+    }
+
+    #[test]
+    fn parses_confidences() {
+        assert_eq!(parse_confidence("0.8").unwrap(), 0.8);
+        assert!(parse_confidence("1.5").is_err());
+        assert!(parse_confidence("-0.1").is_err());
+    }
+
+    #[test]
+    fn scores_exact_matches_fully() {
+        let query = Query {
+            title: "Descend",
+            track_num: Some(3),
+            duration: Some(300),
+        };
+        assert_eq!(query.confidence(&track("Descend", 3, 300)), 1.0);
+        assert!(query.confidence(&track("Descend", 4, 300)) < 1.0);
+        assert!(query.confidence(&track("Doctor", 3, 300)) < DEFAULT_MIN_CONFIDENCE);
+    }
+
+    #[test]
+    fn leaves_out_unknown_signals() {
+        let query = Query {
+            title: "Descend",
+            track_num: None,
+            duration: None,
+        };
+        assert_eq!(query.confidence(&track("Descend", 3, 0)), 1.0);
+    }
+
+    #[test]
+    fn matches_by_track_number_without_a_title() {
+        let album = album(vec![
+            track("Stress", 1, 200),
+            track("Descend", 2, 300),
+            track("Doctor", 3, 240),
+        ]);
+        let query = Query {
+            title: "",
+            track_num: Some(2),
+            duration: None,
+        };
+        assert_eq!(best_name(&query, &album).unwrap().unwrap(), "Descend");
+
+        let query = Query {
+            duration: Some(300),
+            ..query
+        };
+        assert_eq!(best_name(&query, &album).unwrap().unwrap(), "Descend");
+    }
+
+    #[test]
+    fn picks_close_titles() {
+        let album = album(vec![track("Descend", 1, 300), track("Doctor", 2, 240)]);
+        let query = Query {
+            title: "Descend (Remastered)",
+            track_num: None,
+            duration: Some(301),
+        };
+        assert_eq!(best_name(&query, &album).unwrap().unwrap(), "Descend");
+    }
+
+    #[test]
+    fn skips_weak_matches() {
+        let album = album(vec![track("Descend", 1, 300), track("Doctor", 2, 240)]);
+        let query = Query {
+            title: "Savior of the Waking World",
+            track_num: None,
+            duration: None,
+        };
+        assert_eq!(best_name(&query, &album).unwrap(), None);
+    }
+
+    #[test]
+    fn reports_ambiguous_matches() {
+        let album = album(vec![track("Showtime", 1, 0), track("Showtime", 2, 0)]);
+        let query = Query {
+            title: "Showtime",
+            track_num: None,
+            duration: None,
+        };
+        assert!(best_name(&query, &album).is_err());
+    }
+
+    #[test]
+    fn judges_verdicts() {
+        assert_eq!(verdict(vec![0.9, 0.5], 0.65), Verdict::Match);
+        assert_eq!(verdict(vec![0.9, 0.88], 0.65), Verdict::Ambiguous);
+        assert_eq!(verdict(vec![0.6, 0.1], 0.65), Verdict::Unmatched);
+        assert_eq!(verdict(vec![], 0.65), Verdict::Unmatched);
+    }
+}
@@ -0,0 +1,302 @@
+//! merging newly written tags with what a file already had
+use crate::tags::{custom_key, push, set_multi_texts};
+use anyhow::{anyhow, Context, Error, Result};
+use lofty::{ItemKey, ItemValue, Picture, PictureType, Tag, TagItem};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Replace whatever was there
+    Overwrite,
+    /// Only write fields that were empty
+    FillEmpty,
+    /// Add new values after the existing ones
+    Append,
+    /// Never change the field
+    Keep,
+}
+
+impl FromStr for MergePolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "overwrite" => Ok(Self::Overwrite),
+            "fill" | "fill-empty" => Ok(Self::FillEmpty),
+            "append" => Ok(Self::Append),
+            "keep" => Ok(Self::Keep),
+            _ => Err(anyhow!("Bad merge policy {}!", s)),
+        }
+    }
+}
+
+/// Parses a `FIELD=POLICY` pair, like `TrackArtist=keep`.
+pub fn parse_field_policy(s: &str) -> Result<(String, MergePolicy)> {
+    let (field, policy) = s
+        .split_once('=')
+        .with_context(|| format!("expected FIELD=POLICY, got {}", s))?;
+    Ok((field.to_string(), policy.parse()?))
+}
+
+/// Fields are matched ignoring case and separators, so `TrackArtist`, `track-artist` and
+/// `TRACK_ARTIST` are all the same field.
+fn normalize(field: &str) -> String {
+    field
+        .chars()
+        .filter(|x| x.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The name of the field `key` is written to: lofty's name for standard keys, the custom name
+/// otherwise, without the frame or freeform atom it's stored in.
+pub(crate) fn key_name(key: &ItemKey) -> String {
+    match key {
+        ItemKey::Unknown(name) if name.starts_with("----:") => {
+            name.rsplit(':').next().unwrap_or(name).to_string()
+        }
+        ItemKey::Unknown(name) => name
+            .strip_prefix("TXXX:")
+            .or_else(|| name.strip_prefix("WXXX:"))
+            .unwrap_or(name)
+            .to_string(),
+        key => format!("{:?}", key),
+    }
+}
+
+/// `TrackArtist` to `TRACK_ARTIST`.
+fn screaming_snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut was_lowercase = false;
+    for x in name.chars() {
+        if x.is_uppercase() && was_lowercase {
+            out.push('_');
+        }
+        was_lowercase = x.is_lowercase() || x.is_numeric();
+        out.extend(x.to_uppercase());
+    }
+    out
+}
+
+fn merge<T: Clone + PartialEq>(policy: MergePolicy, old: &[T], new: &[T]) -> Vec<T> {
+    match policy {
+        MergePolicy::Overwrite => new.to_vec(),
+        MergePolicy::FillEmpty if old.is_empty() => new.to_vec(),
+        MergePolicy::FillEmpty | MergePolicy::Keep => old.to_vec(),
+        MergePolicy::Append => {
+            let mut merged = old.to_vec();
+            for x in new {
+                if !merged.contains(x) {
+                    merged.push(x.clone());
+                }
+            }
+            merged
+        }
+    }
+}
+
+/// Every text value, with null-separated values split apart, or `None` if any value isn't
+/// text.
+fn texts(values: &[ItemValue]) -> Option<Vec<String>> {
+    let mut texts = vec![];
+    for value in values {
+        match value {
+            ItemValue::Text(x) | ItemValue::Locator(x) => {
+                texts.extend(x.split('\0').map(String::from))
+            }
+            _ => return None,
+        }
+    }
+    Some(texts)
+}
+
+/// How to merge each field with the values a file already had. Front covers are the `Picture`
+/// field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Merge {
+    pub default: MergePolicy,
+    pub fields: HashMap<String, MergePolicy>,
+    /// Copy values that get changed to `ORIGINAL_<FIELD>`, unless there's already a backup
+    pub backup: bool,
+}
+
+impl Default for Merge {
+    fn default() -> Self {
+        Self {
+            default: MergePolicy::Overwrite,
+            fields: HashMap::new(),
+            backup: false,
+        }
+    }
+}
+
+impl Merge {
+    pub fn new(
+        default: MergePolicy,
+        fields: impl IntoIterator<Item = (String, MergePolicy)>,
+        backup: bool,
+    ) -> Self {
+        Self {
+            default,
+            fields: fields
+                .into_iter()
+                .map(|(field, policy)| (normalize(&field), policy))
+                .collect(),
+            backup,
+        }
+    }
+
+    pub fn policy(&self, field: &str) -> MergePolicy {
+        self.fields
+            .get(&normalize(field))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Reconciles `tag`, after edits, with `original`, the tag as it was read.
+    pub fn apply(&self, original: &Tag, tag: &mut Tag) {
+        let mut keys: Vec<ItemKey> = vec![];
+        for item in original.items().iter().chain(tag.items()) {
+            if !keys.contains(item.key()) {
+                keys.push(item.key().clone());
+            }
+        }
+
+        for key in keys {
+            let name = key_name(&key);
+            if name.starts_with("ORIGINAL_") {
+                continue;
+            }
+
+            let values = |tag: &Tag| -> Vec<ItemValue> {
+                tag.get_items(&key).map(|x| x.value().clone()).collect()
+            };
+            let old = values(original);
+            let new = values(tag);
+            if old == new {
+                continue;
+            }
+
+            let policy = self.policy(&name);
+            let merged = match (policy, texts(&old), texts(&new)) {
+                // Appended values are written back the way the format stores several, since
+                // ID3v2 and APE can't repeat a field
+                (MergePolicy::Append, Some(old), Some(new)) => {
+                    set_multi_texts(tag, key.clone(), merge(policy, &old, &new));
+                    values(tag)
+                }
+                _ => {
+                    let merged = merge(policy, &old, &new);
+                    if merged != new {
+                        tag.remove_key(&key);
+                        for value in merged.iter().cloned() {
+                            push(tag, TagItem::new(key.clone(), value));
+                        }
+                    }
+                    merged
+                }
+            };
+
+            if self.backup && merged != old {
+                self.backup(original, tag, &name, &old);
+            }
+        }
+
+        let covers = |tag: &Tag| -> Vec<Picture> {
+            tag.pictures()
+                .iter()
+                .filter(|x| x.pic_type() == PictureType::CoverFront)
+                .cloned()
+                .collect()
+        };
+        let old = covers(original);
+        let new = covers(tag);
+        let merged = merge(self.policy("Picture"), &old, &new);
+        if merged != new {
+            tag.remove_picture_type(PictureType::CoverFront);
+            for picture in merged {
+                tag.push_picture(picture);
+            }
+        }
+    }
+
+    fn backup(&self, original: &Tag, tag: &mut Tag, name: &str, old: &[ItemValue]) {
+        let key = custom_key(tag, &format!("ORIGINAL_{}", screaming_snake_case(name)));
+        if old.is_empty() || original.get_items(&key).next().is_some() {
+            return;
+        }
+
+        let old = old
+            .iter()
+            .filter_map(|x| match x {
+                ItemValue::Text(x) | ItemValue::Locator(x) => Some(x.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        set_multi_texts(tag, key, old);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::set_texts;
+    use lofty::TagType;
+
+    const TAG_TYPES: [TagType; 4] = [
+        TagType::Id3v2,
+        TagType::Ape,
+        TagType::VorbisComments,
+        TagType::Mp4Ilst,
+    ];
+
+    fn texts(tag: &Tag, key: &ItemKey) -> Vec<String> {
+        tag.get_items(key)
+            .filter_map(|x| x.value().text().map(String::from))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_custom_fields() {
+        let merge = Merge::new(
+            MergePolicy::Overwrite,
+            vec![("hsmusic-color".to_string(), MergePolicy::Keep)],
+            false,
+        );
+
+        for tag_type in TAG_TYPES {
+            let mut original = Tag::new(tag_type);
+            let key = custom_key(&original, "HSMUSIC_COLOR");
+            set_texts(&mut original, key.clone(), Some("#0060ff".to_string()));
+
+            let mut tag = original.clone();
+            set_texts(&mut tag, key.clone(), Some("#ff6000".to_string()));
+            merge.apply(&original, &mut tag);
+            assert_eq!(texts(&tag, &key), ["#0060ff"], "{:?}", tag_type);
+        }
+    }
+
+    #[test]
+    fn backs_up_changed_fields() {
+        let merge = Merge::new(MergePolicy::Overwrite, vec![], true);
+
+        for tag_type in TAG_TYPES {
+            let mut original = Tag::new(tag_type);
+            original.insert_text(ItemKey::TrackArtist, "Toby Fox".to_string());
+
+            let mut tag = original.clone();
+            tag.insert_text(ItemKey::TrackArtist, "Toby Fox, Jit".to_string());
+            merge.apply(&original, &mut tag);
+
+            let backup = custom_key(&tag, "ORIGINAL_TRACK_ARTIST");
+            assert_eq!(texts(&tag, &backup), ["Toby Fox"], "{:?}", tag_type);
+            assert_eq!(
+                texts(&tag, &ItemKey::TrackArtist),
+                ["Toby Fox, Jit"],
+                "{:?}",
+                tag_type
+            );
+        }
+    }
+}
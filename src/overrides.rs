@@ -0,0 +1,137 @@
+//! user-editable fixes for matches the fuzzy matcher gets wrong
+use crate::matching::normalize;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+const DEFAULT: &str = include_str!("overrides.yaml");
+
+/// Always matches files tagged with `album` and `title` to the hsmusic track named `track`,
+/// from `hsmusic-album` if given, or else from whichever album has it first.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Pin {
+    pub album: String,
+    pub title: String,
+    pub track: String,
+    #[serde(default)]
+    pub hsmusic_album: Option<String>,
+}
+
+/// Album aliases, track pins and albums excluded from Bandcamp URL matching, e.g.
+///
+/// ```yaml
+/// album-aliases:
+///   "Homestuck - Strife!": "Strife!"
+/// album-suffixes:
+///   - " [UNOFFICIAL ALBUM]"
+/// pins:
+///   - album: "Homestuck Vol. 9-10 (with [S] Collide. and Act 7)"
+///     title: Frustracean
+///     track: Frustracean
+/// excluded-albums:
+///   - Homestuck Vol. 1
+/// ```
+///
+/// Names are compared once normalized, so case, punctuation and the like don't matter.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Overrides {
+    /// Tagged album names, and the hsmusic album they are
+    pub album_aliases: HashMap<String, String>,
+    /// Stripped from the end of tagged album names
+    pub album_suffixes: Vec<String>,
+    pub pins: Vec<Pin>,
+    /// Albums whose tracks are never matched by Bandcamp URL, like collections that link to
+    /// where their tracks were first released
+    pub excluded_albums: Vec<String>,
+}
+
+impl Overrides {
+    /// The built-in overrides.
+    pub fn builtin() -> Self {
+        let overrides: Self =
+            serde_yaml::from_str(DEFAULT).expect("built-in overrides are invalid");
+        overrides.normalize_aliases()
+    }
+
+    /// Reads overrides from a YAML or JSON file.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let string = read_to_string(path)
+            .with_context(|| format!("failed to read overrides {}", path.display()))?;
+        let overrides: Self = serde_yaml::from_str(&string)
+            .with_context(|| format!("failed to parse overrides {}", path.display()))?;
+        Ok(overrides.normalize_aliases())
+    }
+
+    fn normalize_aliases(self) -> Self {
+        Self {
+            album_aliases: self
+                .album_aliases
+                .into_iter()
+                .map(|(from, to)| (normalize(&from), to))
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Where the overrides for `bandcamp_json` are: `overrides.yaml` in the same directory.
+    pub fn path_beside(bandcamp_json: &Path) -> PathBuf {
+        bandcamp_json.with_file_name("overrides.yaml")
+    }
+
+    /// The built-in overrides, extended by the overrides file beside `bandcamp_json` if there
+    /// is one.
+    pub fn load_beside(bandcamp_json: &Path) -> Result<Self> {
+        let mut overrides = Self::builtin();
+        let path = Self::path_beside(bandcamp_json);
+        if path.is_file() {
+            overrides.extend(Self::read(path)?);
+        }
+        Ok(overrides)
+    }
+
+    /// Adds `other`'s overrides. Its aliases and pins take precedence.
+    pub fn extend(&mut self, other: Self) {
+        self.album_aliases.extend(other.album_aliases);
+        self.album_suffixes.extend(other.album_suffixes);
+        let mut pins = other.pins;
+        pins.append(&mut self.pins);
+        self.pins = pins;
+        self.excluded_albums.extend(other.excluded_albums);
+    }
+
+    /// The hsmusic name for a tagged album name.
+    pub fn album_name<'s>(&'s self, album: &'s str) -> &'s str {
+        let mut album = album;
+        while let Some(stripped) = self
+            .album_suffixes
+            .iter()
+            .filter(|x| !x.is_empty())
+            .find_map(|x| album.strip_suffix(&x[..]))
+        {
+            album = stripped;
+        }
+
+        self.album_aliases
+            .get(&normalize(album))
+            .map_or(album, |x| &x[..])
+    }
+
+    /// The pin for a tagged album and title, if there is one.
+    pub fn pin(&self, album: &str, title: &str) -> Option<&Pin> {
+        let album = normalize(album);
+        let title = normalize(title);
+        self.pins
+            .iter()
+            .find(|x| normalize(&x.album) == album && normalize(&x.title) == title)
+    }
+
+    pub fn is_excluded(&self, album: &str) -> bool {
+        let album = normalize(album);
+        self.excluded_albums.iter().any(|x| normalize(x) == album)
+    }
+}
@@ -0,0 +1,324 @@
+//! mapping profiles, which say which tag field each piece of hsmusic data is written to
+use crate::tags::custom_key;
+use anyhow::{anyhow, Context, Error, Result};
+use lofty::{ItemKey, Tag, TagType};
+use serde::Deserialize;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// hsmusic data that can be written to a field of the profile's choosing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    TrackArtist,
+    AlbumTitle,
+    TrackNumber,
+    TrackTotal,
+    AlbumArtist,
+    /// The album's release date
+    ReleaseDate,
+    /// When a track was first released, if that was somewhere else
+    OriginalReleaseDate,
+    /// The track's first release link
+    TrackUrl,
+    /// The track's other release links, and its album's
+    ReleaseUrls,
+    /// The track artist's first link
+    ArtistUrl,
+    /// Artists credited with a role, like vocals
+    Performers,
+    /// Everyone else who contributed to a track
+    Contributors,
+    /// Commentary, when written to the comment field
+    Comment,
+    /// Commentary, when written to a custom field
+    Commentary,
+    Lyrics,
+    Genre,
+    Grouping,
+    /// The group a track is in within its album
+    GroupSubtitle,
+    Color,
+    References,
+    ReferencedBy,
+    CoverArtDate,
+    HsmusicAlbum,
+    HsmusicTrack,
+    BandcampUrl,
+}
+
+impl Field {
+    fn default_key(self, tag_type: TagType) -> &'static str {
+        match self {
+            Self::TrackArtist => "TrackArtist",
+            Self::AlbumTitle => "AlbumTitle",
+            Self::TrackNumber => "TrackNumber",
+            Self::TrackTotal => "TrackTotal",
+            Self::AlbumArtist => "AlbumArtist",
+            Self::ReleaseDate if tag_type == TagType::Ape => "Year",
+            Self::ReleaseDate => "RecordingDate",
+            Self::OriginalReleaseDate if matches!(tag_type, TagType::Mp4Ilst | TagType::Ape) => {
+                "ORIGINALDATE"
+            }
+            Self::OriginalReleaseDate => "OriginalReleaseDate",
+            Self::TrackUrl if tag_type == TagType::Id3v2 => "AudioSourceURL",
+            Self::TrackUrl => "WEBSITE",
+            Self::ReleaseUrls => "URL",
+            Self::ArtistUrl if tag_type == TagType::Id3v2 => "TrackArtistURL",
+            Self::ArtistUrl => "ARTIST_URL",
+            Self::Performers if tag_type == TagType::Id3v2 => "MusicianCredits",
+            Self::Performers | Self::Contributors if tag_type == TagType::Mp4Ilst => "PERFORMER",
+            Self::Performers => "Performer",
+            Self::Contributors if tag_type == TagType::Id3v2 => "InvolvedPeople",
+            Self::Contributors => "Performer",
+            Self::Comment => "Comment",
+            Self::Commentary => "HSMUSIC_COMMENTARY",
+            Self::Lyrics => "Lyrics",
+            Self::Genre => "Genre",
+            Self::Grouping => "ContentGroup",
+            Self::GroupSubtitle => "SetSubtitle",
+            Self::Color => "HSMUSIC_COLOR",
+            Self::References => "HSMUSIC_REFERENCES",
+            Self::ReferencedBy => "HSMUSIC_REFERENCED_BY",
+            Self::CoverArtDate => "HSMUSIC_COVER_ART_DATE",
+            Self::HsmusicAlbum => "HSMUSIC_ALBUM",
+            Self::HsmusicTrack => "HSMUSIC_TRACK",
+            Self::BandcampUrl if tag_type == TagType::Id3v2 => "AudioFileURL",
+            Self::BandcampUrl => "BANDCAMP_URL",
+        }
+    }
+}
+
+/// lofty's names for the standard keys a field can be mapped to.
+fn standard_key(name: &str) -> Option<ItemKey> {
+    Some(match name {
+        "AlbumTitle" => ItemKey::AlbumTitle,
+        "SetSubtitle" => ItemKey::SetSubtitle,
+        "ContentGroup" => ItemKey::ContentGroup,
+        "TrackTitle" => ItemKey::TrackTitle,
+        "TrackSubtitle" => ItemKey::TrackSubtitle,
+        "OriginalAlbumTitle" => ItemKey::OriginalAlbumTitle,
+        "OriginalArtist" => ItemKey::OriginalArtist,
+        "OriginalLyricist" => ItemKey::OriginalLyricist,
+        "AlbumTitleSortOrder" => ItemKey::AlbumTitleSortOrder,
+        "AlbumArtistSortOrder" => ItemKey::AlbumArtistSortOrder,
+        "TrackTitleSortOrder" => ItemKey::TrackTitleSortOrder,
+        "TrackArtistSortOrder" => ItemKey::TrackArtistSortOrder,
+        "ComposerSortOrder" => ItemKey::ComposerSortOrder,
+        "AlbumArtist" => ItemKey::AlbumArtist,
+        "TrackArtist" => ItemKey::TrackArtist,
+        "Arranger" => ItemKey::Arranger,
+        "Writer" => ItemKey::Writer,
+        "Composer" => ItemKey::Composer,
+        "Conductor" => ItemKey::Conductor,
+        "Engineer" => ItemKey::Engineer,
+        "InvolvedPeople" => ItemKey::InvolvedPeople,
+        "Lyricist" => ItemKey::Lyricist,
+        "MixDj" => ItemKey::MixDj,
+        "MixEngineer" => ItemKey::MixEngineer,
+        "MusicianCredits" => ItemKey::MusicianCredits,
+        "Performer" => ItemKey::Performer,
+        "Producer" => ItemKey::Producer,
+        "Publisher" => ItemKey::Publisher,
+        "Label" => ItemKey::Label,
+        "InternetRadioStationName" => ItemKey::InternetRadioStationName,
+        "InternetRadioStationOwner" => ItemKey::InternetRadioStationOwner,
+        "Remixer" => ItemKey::Remixer,
+        "DiscNumber" => ItemKey::DiscNumber,
+        "DiscTotal" => ItemKey::DiscTotal,
+        "TrackNumber" => ItemKey::TrackNumber,
+        "TrackTotal" => ItemKey::TrackTotal,
+        "RecordingDate" => ItemKey::RecordingDate,
+        "Year" => ItemKey::Year,
+        "OriginalReleaseDate" => ItemKey::OriginalReleaseDate,
+        "ISRC" => ItemKey::ISRC,
+        "Barcode" => ItemKey::Barcode,
+        "CatalogNumber" => ItemKey::CatalogNumber,
+        "FlagCompilation" => ItemKey::FlagCompilation,
+        "FileType" => ItemKey::FileType,
+        "FileOwner" => ItemKey::FileOwner,
+        "TaggingTime" => ItemKey::TaggingTime,
+        "Length" => ItemKey::Length,
+        "OriginalFileName" => ItemKey::OriginalFileName,
+        "OriginalMediaType" => ItemKey::OriginalMediaType,
+        "EncodedBy" => ItemKey::EncodedBy,
+        "EncoderSoftware" => ItemKey::EncoderSoftware,
+        "EncoderSettings" => ItemKey::EncoderSettings,
+        "EncodingTime" => ItemKey::EncodingTime,
+        "AudioFileURL" => ItemKey::AudioFileURL,
+        "AudioSourceURL" => ItemKey::AudioSourceURL,
+        "CommercialInformationURL" => ItemKey::CommercialInformationURL,
+        "CopyrightURL" => ItemKey::CopyrightURL,
+        "TrackArtistURL" => ItemKey::TrackArtistURL,
+        "RadioStationURL" => ItemKey::RadioStationURL,
+        "PaymentURL" => ItemKey::PaymentURL,
+        "PublisherURL" => ItemKey::PublisherURL,
+        "Genre" => ItemKey::Genre,
+        "InitialKey" => ItemKey::InitialKey,
+        "Mood" => ItemKey::Mood,
+        "BPM" => ItemKey::BPM,
+        "CopyrightMessage" => ItemKey::CopyrightMessage,
+        "License" => ItemKey::License,
+        "Comment" => ItemKey::Comment,
+        "Description" => ItemKey::Description,
+        "Language" => ItemKey::Language,
+        "Script" => ItemKey::Script,
+        "Lyrics" => ItemKey::Lyrics,
+        _ => return None,
+    })
+}
+
+/// The key for each [`Field`], if it isn't the default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Keys {
+    pub track_artist: Option<String>,
+    pub album_title: Option<String>,
+    pub track_number: Option<String>,
+    pub track_total: Option<String>,
+    pub album_artist: Option<String>,
+    pub release_date: Option<String>,
+    pub original_release_date: Option<String>,
+    pub track_url: Option<String>,
+    pub release_urls: Option<String>,
+    pub artist_url: Option<String>,
+    pub performers: Option<String>,
+    pub contributors: Option<String>,
+    pub comment: Option<String>,
+    pub commentary: Option<String>,
+    pub lyrics: Option<String>,
+    pub genre: Option<String>,
+    pub grouping: Option<String>,
+    pub group_subtitle: Option<String>,
+    pub color: Option<String>,
+    pub references: Option<String>,
+    pub referenced_by: Option<String>,
+    pub cover_art_date: Option<String>,
+    pub hsmusic_album: Option<String>,
+    pub hsmusic_track: Option<String>,
+    pub bandcamp_url: Option<String>,
+}
+
+impl Keys {
+    fn get(&self, field: Field) -> Option<&str> {
+        match field {
+            Field::TrackArtist => &self.track_artist,
+            Field::AlbumTitle => &self.album_title,
+            Field::TrackNumber => &self.track_number,
+            Field::TrackTotal => &self.track_total,
+            Field::AlbumArtist => &self.album_artist,
+            Field::ReleaseDate => &self.release_date,
+            Field::OriginalReleaseDate => &self.original_release_date,
+            Field::TrackUrl => &self.track_url,
+            Field::ReleaseUrls => &self.release_urls,
+            Field::ArtistUrl => &self.artist_url,
+            Field::Performers => &self.performers,
+            Field::Contributors => &self.contributors,
+            Field::Comment => &self.comment,
+            Field::Commentary => &self.commentary,
+            Field::Lyrics => &self.lyrics,
+            Field::Genre => &self.genre,
+            Field::Grouping => &self.grouping,
+            Field::GroupSubtitle => &self.group_subtitle,
+            Field::Color => &self.color,
+            Field::References => &self.references,
+            Field::ReferencedBy => &self.referenced_by,
+            Field::CoverArtDate => &self.cover_art_date,
+            Field::HsmusicAlbum => &self.hsmusic_album,
+            Field::HsmusicTrack => &self.hsmusic_track,
+            Field::BandcampUrl => &self.bandcamp_url,
+        }
+        .as_deref()
+    }
+}
+
+/// Which key each [`Field`] is written to, overall and per tag format. Keys are either one of
+/// lofty's standard key names (like `Comment` or `ContentGroup`), which are mapped to each
+/// format's own name, or the name of a custom field. `none` skips the field entirely. Anything
+/// that isn't mapped uses the default key.
+///
+/// ```toml
+/// [fields]
+/// commentary = "Comment"
+///
+/// [mp4]
+/// commentary = "Description"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub fields: Keys,
+    pub id3v2: Keys,
+    pub vorbis: Keys,
+    pub mp4: Keys,
+    pub ape: Keys,
+}
+
+const BUILTIN: &[(&str, &str)] = &[
+    ("foobar2000", include_str!("profiles/foobar2000.toml")),
+    ("musicbee", include_str!("profiles/musicbee.toml")),
+    ("navidrome", include_str!("profiles/navidrome.toml")),
+    ("itunes", include_str!("profiles/itunes.toml")),
+];
+
+impl Profile {
+    /// The names of the built-in profiles.
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN.iter().map(|(name, _)| *name)
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (_, profile) = BUILTIN.iter().find(|(x, _)| *x == name)?;
+        Some(toml::from_str(profile).expect("built-in profile is invalid"))
+    }
+
+    /// Reads a profile from a TOML or JSON file.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let string = read_to_string(path)
+            .with_context(|| format!("failed to read profile {}", path.display()))?;
+
+        let profile = match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => serde_json::from_str(&string).map_err(Error::from),
+            _ => toml::from_str(&string).map_err(Error::from),
+        };
+        profile.with_context(|| format!("failed to parse profile {}", path.display()))
+    }
+
+    /// Loads a built-in profile by name, or else a profile file.
+    pub fn load(name_or_path: &str) -> Result<Self> {
+        match Self::builtin(name_or_path) {
+            Some(profile) => Ok(profile),
+            None if Path::new(name_or_path).is_file() => Self::read(name_or_path),
+            None => Err(anyhow!(
+                "{} isn't a profile file or one of {}",
+                name_or_path,
+                Self::builtin_names().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+
+    fn format_keys(&self, tag_type: TagType) -> Option<&Keys> {
+        match tag_type {
+            TagType::Id3v2 => Some(&self.id3v2),
+            TagType::VorbisComments => Some(&self.vorbis),
+            TagType::Mp4Ilst => Some(&self.mp4),
+            TagType::Ape => Some(&self.ape),
+            _ => None,
+        }
+    }
+
+    /// The key `field` is written to in `tag`, or `None` if it shouldn't be written.
+    pub fn key(&self, tag: &Tag, field: Field) -> Option<ItemKey> {
+        let tag_type = tag.tag_type();
+        let name = self
+            .format_keys(tag_type)
+            .and_then(|x| x.get(field))
+            .or_else(|| self.fields.get(field))
+            .unwrap_or_else(|| field.default_key(tag_type));
+
+        match name {
+            "" | "none" => None,
+            name => Some(standard_key(name).unwrap_or_else(|| custom_key(tag, name))),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! reports of what happened to each file, what it matched and which of its tags change, as a
+//! table, JSON or CSV
+use crate::merge::key_name;
+use anyhow::{anyhow, Error, Result};
+use lofty::{ItemKey, ItemValue, PictureType, Tag};
+use serde::Serialize;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// How a file was matched to its hsmusic track.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// A manual match for the file
+    Manual,
+    /// The hsmusic album and track recorded in its tags
+    Ids,
+    /// Its album, title and track number tags
+    Tags,
+    /// Its path, for files without tags
+    Path,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Manual => "manual",
+            Self::Ids => "ids",
+            Self::Tags => "tags",
+            Self::Path => "path",
+        })
+    }
+}
+
+/// What happened to a file. Failures are whichever step the file got to, with the error in the
+/// report.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// Matched, and written unless it's a dry run
+    Matched,
+    /// Skipped, because it isn't an audio file
+    NotAudio,
+    /// Has no tags, and no path template matched
+    NoTag,
+    /// Has tags that didn't match a track
+    Unmatched,
+    /// Couldn't be copied, or its tags or lyrics couldn't be written
+    FailedWrite,
+    /// Failed some other way
+    #[default]
+    Failed,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Matched => "matched",
+            Self::NotAudio => "not audio",
+            Self::NoTag => "no tag",
+            Self::Unmatched => "unmatched",
+            Self::FailedWrite => "failed write",
+            Self::Failed => "failed",
+        })
+    }
+}
+
+/// What was found out about one file, and what was (or would be) done to it.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MatchReport {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+    /// The album, title and track number the file's tags or path gave
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track_num: Option<usize>,
+    pub hsmusic_album: Option<String>,
+    pub hsmusic_track: Option<String>,
+    pub strategy: Option<Strategy>,
+    /// How confident a fuzzy match is, from 0 to 1. Exact matches don't have one
+    pub confidence: Option<f64>,
+    pub art: Option<PathBuf>,
+    /// The fields that change
+    pub changes: Vec<String>,
+    /// The error, then each of its causes
+    pub errors: Vec<String>,
+    /// Things that didn't stop the file from being written, but may be wrong
+    pub warnings: Vec<String>,
+}
+
+/// Every file a run went through, along with the broken hsmusic-data it skipped.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RunReport {
+    pub warnings: Vec<String>,
+    pub files: Vec<MatchReport>,
+}
+
+impl RunReport {
+    pub fn count(&self, outcome: Outcome) -> usize {
+        self.files.iter().filter(|x| x.outcome == outcome).count()
+    }
+
+    /// The files that ran into an error.
+    pub fn problems(&self) -> impl Iterator<Item = &MatchReport> {
+        self.files.iter().filter(|x| !x.errors.is_empty())
+    }
+
+    /// How many warnings there are, for the run and every file.
+    pub fn warning_count(&self) -> usize {
+        self.warnings.len() + self.files.iter().map(|x| x.warnings.len()).sum::<usize>()
+    }
+
+    /// How many files had each outcome, and how many warnings there are, like
+    /// `10 matched, 2 not audio, 1 warning`.
+    pub fn summary(&self) -> String {
+        let outcomes = [
+            Outcome::Matched,
+            Outcome::NotAudio,
+            Outcome::NoTag,
+            Outcome::Unmatched,
+            Outcome::FailedWrite,
+            Outcome::Failed,
+        ];
+        let mut counts: Vec<_> = outcomes
+            .iter()
+            .map(|&x| (x, self.count(x)))
+            .filter(|&(_, count)| count > 0)
+            .map(|(outcome, count)| format!("{} {}", count, outcome))
+            .collect();
+        match self.warning_count() {
+            0 => {}
+            1 => counts.push("1 warning".to_string()),
+            count => counts.push(format!("{} warnings", count)),
+        }
+
+        if counts.is_empty() {
+            "no files".to_string()
+        } else {
+            counts.join(", ")
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(anyhow!("Bad report format {}!", s)),
+        }
+    }
+}
+
+/// The names of the fields that differ between `before` and `after`, with front covers as
+/// `Picture`.
+pub fn changed_fields(before: &Tag, after: &Tag) -> Vec<String> {
+    let mut keys: Vec<&ItemKey> = vec![];
+    for item in before.items().iter().chain(after.items()) {
+        if !keys.contains(&item.key()) {
+            keys.push(item.key());
+        }
+    }
+
+    let values = |tag: &Tag, key| -> Vec<ItemValue> {
+        tag.get_items(key).map(|x| x.value().clone()).collect()
+    };
+    let mut changes: Vec<String> = keys
+        .into_iter()
+        .filter(|&key| values(before, key) != values(after, key))
+        .map(key_name)
+        .collect();
+
+    let covers = |tag: &Tag| {
+        tag.pictures()
+            .iter()
+            .filter(|x| x.pic_type() == PictureType::CoverFront)
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    if covers(before) != covers(after) {
+        changes.push("Picture".to_string());
+    }
+
+    changes
+}
+
+const HEADERS: &[&str] = &[
+    "path",
+    "outcome",
+    "album",
+    "title",
+    "track",
+    "hsmusic album",
+    "hsmusic track",
+    "strategy",
+    "confidence",
+    "art",
+    "changes",
+    "errors",
+    "warnings",
+];
+
+impl MatchReport {
+    fn columns(&self) -> Vec<String> {
+        let text = |x: &Option<String>| x.clone().unwrap_or_default();
+
+        vec![
+            self.path.display().to_string(),
+            self.outcome.to_string(),
+            text(&self.album),
+            text(&self.title),
+            self.track_num.map(|x| x.to_string()).unwrap_or_default(),
+            text(&self.hsmusic_album),
+            text(&self.hsmusic_track),
+            self.strategy.map(|x| x.to_string()).unwrap_or_default(),
+            self.confidence
+                .map(|x| format!("{:.0}%", x * 100.0))
+                .unwrap_or_default(),
+            self.art
+                .as_ref()
+                .map(|x| x.display().to_string())
+                .unwrap_or_default(),
+            self.changes.join(", "),
+            self.errors.join(": "),
+            self.warnings.join("; "),
+        ]
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes `reports` to `out` in `format`.
+pub fn write_report(
+    reports: &[MatchReport],
+    format: ReportFormat,
+    mut out: impl Write,
+) -> Result<()> {
+    match format {
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, reports)?;
+            writeln!(out)?;
+        }
+        ReportFormat::Csv => {
+            writeln!(out, "{}", HEADERS.join(","))?;
+            for report in reports {
+                let columns: Vec<_> = report.columns().iter().map(|x| csv_field(x)).collect();
+                writeln!(out, "{}", columns.join(","))?;
+            }
+        }
+        ReportFormat::Table => {
+            let rows: Vec<_> = reports.iter().map(MatchReport::columns).collect();
+            let mut widths: Vec<_> = HEADERS.iter().map(|x| x.chars().count()).collect();
+            for row in &rows {
+                for (width, column) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(column.chars().count());
+                }
+            }
+
+            let headers = HEADERS.iter().map(|x| x.to_string()).collect();
+            for row in std::iter::once(headers).chain(rows) {
+                let line = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(column, &width)| format!("{:width$}", column, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                writeln!(out, "{}", line.trim_end())?;
+            }
+        }
+    }
+
+    Ok(())
+}
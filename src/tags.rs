@@ -0,0 +1,900 @@
+//! writing hsmusic data into tags, smoothing over the differences between formats
+use crate::hsmusic::{Album, Contributor, Track};
+use crate::profile::{Field, Profile};
+use crate::ArtistStyle;
+use chrono::NaiveDate;
+use lofty::id3::v2::{EncodedTextFrame, Frame, FrameFlags, FrameValue, Id3v2Tag, TextEncoding};
+use lofty::iff::{AiffFile, WavFile};
+use lofty::mp3::Mp3File;
+use lofty::{AudioFile, FileType, ItemKey, ItemValue, Tag, TagExt, TagItem, TagType, TaggedFile};
+use std::fs::File;
+use std::path::Path;
+use url::Url;
+
+/// The key for a custom field: a TXXX frame described by the name for ID3v2, a freeform atom for
+/// MP4, and the name as is everywhere else.
+pub fn custom_key(tag: &Tag, name: &str) -> ItemKey {
+    match tag.tag_type() {
+        TagType::Id3v2 => ItemKey::Unknown(format!("TXXX:{}", name)),
+        TagType::Mp4Ilst => ItemKey::Unknown(format!("----:com.apple.iTunes:{}", name)),
+        _ => ItemKey::Unknown(name.to_string()),
+    }
+}
+
+/// Adds `item` to `tag`. lofty rejects every custom key, so those are pushed without checking
+/// them against the format's mapping.
+pub(crate) fn push(tag: &mut Tag, item: TagItem) {
+    match item.key() {
+        ItemKey::Unknown(_) => tag.push_item_unchecked(item),
+        _ => {
+            tag.push_item(item);
+        }
+    }
+}
+
+/// Replaces every value of `key` with `values`.
+pub fn set_texts(tag: &mut Tag, key: ItemKey, values: impl IntoIterator<Item = String>) {
+    tag.remove_key(&key);
+    for value in values {
+        let value = url_or_text(tag, &key, value);
+        push(tag, TagItem::new(key.clone(), value));
+    }
+}
+
+/// Sets `field` in whichever key `profile` maps it to.
+pub fn set_field(tag: &mut Tag, profile: &Profile, field: Field, value: String) {
+    if let Some(key) = profile.key(tag, field) {
+        set_texts(tag, key, Some(value));
+    }
+}
+
+/// Sets every value of `field` in whichever key `profile` maps it to.
+pub fn set_multi_field(tag: &mut Tag, profile: &Profile, field: Field, values: Vec<String>) {
+    if let Some(key) = profile.key(tag, field) {
+        set_multi_texts(tag, key, values);
+    }
+}
+
+/// ID3v2 URL frames need locators rather than text.
+fn url_or_text(tag: &Tag, key: &ItemKey, value: String) -> ItemValue {
+    match (tag.tag_type(), key) {
+        (
+            TagType::Id3v2,
+            ItemKey::AudioFileURL
+            | ItemKey::AudioSourceURL
+            | ItemKey::TrackArtistURL
+            | ItemKey::CommercialInformationURL,
+        ) => ItemValue::Locator(value),
+        _ => ItemValue::Text(value),
+    }
+}
+
+/// Replaces every value of `key` with `values`, using a single null-separated value for formats
+/// that don't allow repeating a field.
+pub fn set_multi_texts(tag: &mut Tag, key: ItemKey, values: impl IntoIterator<Item = String>) {
+    match tag.tag_type() {
+        TagType::Id3v2 | TagType::Ape => {
+            let values: Vec<_> = values.into_iter().collect();
+            let value = (!values.is_empty()).then(|| values.join("\0"));
+            set_texts(tag, key, value);
+        }
+        _ => set_texts(tag, key, values),
+    }
+}
+
+pub fn write_artists(tag: &mut Tag, key: ItemKey, artists: &[&str], style: &ArtistStyle) {
+    match (style, artists) {
+        (_, []) => {}
+        (ArtistStyle::Joined(separator), artists) => {
+            set_texts(tag, key, Some(artists.join(separator)));
+        }
+        (ArtistStyle::Multiple, artists) => {
+            set_multi_texts(tag, key, artists.iter().map(|x| x.to_string()));
+        }
+        (ArtistStyle::Featuring, [artist]) => {
+            set_texts(tag, key, Some(artist.to_string()));
+        }
+        (ArtistStyle::Featuring, [first, rest @ ..]) => {
+            set_texts(
+                tag,
+                key,
+                Some(format!("{} feat. {}", first, rest.join(", "))),
+            );
+        }
+    }
+}
+
+fn track_artists<'a>(track: &Track<'a>) -> Vec<&'a str> {
+    track.artists.iter().flatten().map(|x| x.who).collect()
+}
+
+/// The album's own artists, or else the artists shared by every track. Empty for albums where
+/// tracks are by different artists.
+pub fn album_artists<'a>(album: &Album<'a>) -> Vec<&'a str> {
+    if let Some(artists) = &album.artists {
+        return artists.iter().map(|x| x.who).collect();
+    }
+
+    let mut tracks = album.tracks.iter().map(track_artists);
+    match tracks.next() {
+        Some(first) if tracks.all(|x| x == first) => first,
+        _ => vec![],
+    }
+}
+
+/// Whether tracks on the album are by different artists, with no album artist to tie them
+/// together.
+pub fn is_compilation(album: &Album) -> bool {
+    album.artists.is_none()
+        && album.tracks.iter().any(|x| x.artists.is_some())
+        && album_artists(album).is_empty()
+}
+
+/// The track's disc number and the album's disc count, numbering discs by group section.
+pub fn disc_number(album: &Album, track: &Track) -> Option<(usize, usize)> {
+    if !album.uses_groups {
+        return None;
+    }
+
+    let mut groups: Vec<&str> = vec![];
+    for x in &album.tracks {
+        if !groups.contains(&x.group) {
+            groups.push(x.group);
+        }
+    }
+
+    let disc = groups.iter().position(|&x| x == track.group)?;
+    Some((disc + 1, groups.len()))
+}
+
+pub fn write_album(
+    tag: &mut Tag,
+    profile: &Profile,
+    album: &Album,
+    track: &Track,
+    artist_style: Option<&ArtistStyle>,
+    disc_numbers: bool,
+) {
+    set_field(tag, profile, Field::AlbumTitle, album.name.to_string());
+    set_field(
+        tag,
+        profile,
+        Field::TrackNumber,
+        track.track_num.to_string(),
+    );
+    set_field(
+        tag,
+        profile,
+        Field::TrackTotal,
+        album.tracks.len().to_string(),
+    );
+    set_field(
+        tag,
+        profile,
+        Field::ReleaseDate,
+        album.date.format("%F").to_string(),
+    );
+
+    if let Some(key) = profile.key(tag, Field::AlbumArtist) {
+        let style = artist_style
+            .cloned()
+            .unwrap_or_else(|| ArtistStyle::Joined(", ".to_string()));
+        write_artists(tag, key, &album_artists(album), &style);
+    }
+
+    if is_compilation(album) {
+        tag.insert_text(ItemKey::FlagCompilation, "1".to_string());
+    } else {
+        tag.remove_key(&ItemKey::FlagCompilation);
+    }
+
+    if disc_numbers {
+        if let Some((disc, total)) = disc_number(album, track) {
+            tag.insert_text(ItemKey::DiscNumber, disc.to_string());
+            tag.insert_text(ItemKey::DiscTotal, total.to_string());
+        }
+    }
+}
+
+fn credit(contributor: &Contributor) -> String {
+    match contributor.what {
+        Some(what) => format!("{} ({})", contributor.who, what),
+        None => contributor.who.to_string(),
+    }
+}
+
+/// ID3v2.4 involved people lists are role/name pairs, all separated by nulls.
+fn id3_credits<'a>(
+    contributors: impl IntoIterator<Item = &'a Contributor<'a>>,
+    default_role: &str,
+) -> Option<String> {
+    let pairs: Vec<_> = contributors
+        .into_iter()
+        .map(|x| format!("{}\0{}", x.what.unwrap_or(default_role), x.who))
+        .collect();
+
+    if pairs.is_empty() {
+        None
+    } else {
+        Some(pairs.join("\0"))
+    }
+}
+
+/// Writes `contributors` to `key`, as role/name pairs for ID3v2 credit lists.
+fn set_credits(tag: &mut Tag, key: ItemKey, contributors: Vec<&Contributor>, default_role: &str) {
+    match (tag.tag_type(), &key) {
+        (TagType::Id3v2, ItemKey::MusicianCredits | ItemKey::InvolvedPeople) => {
+            set_texts(tag, key, id3_credits(contributors, default_role))
+        }
+        _ => set_multi_texts(tag, key, contributors.into_iter().map(credit)),
+    }
+}
+
+/// Writes role-aware credits: artists with a role as performers, and every contributor. By
+/// default, they go in TMCL and TIPL for ID3v2, and together in the performer field elsewhere,
+/// which is a PERFORMER freeform atom for MP4.
+pub fn write_credits(tag: &mut Tag, profile: &Profile, track: &Track) {
+    let performers: Vec<_> = track
+        .artists
+        .iter()
+        .flatten()
+        .filter(|x| x.what.is_some())
+        .collect();
+    let contributors: Vec<_> = track.contributors.iter().collect();
+
+    match (
+        profile.key(tag, Field::Performers),
+        profile.key(tag, Field::Contributors),
+    ) {
+        (Some(performers_key), Some(contributors_key)) if performers_key == contributors_key => {
+            let credits = performers.into_iter().chain(contributors).collect();
+            set_credits(tag, performers_key, credits, "performer");
+        }
+        (performers_key, contributors_key) => {
+            if let Some(key) = performers_key {
+                set_credits(tag, key, performers, "performer");
+            }
+            if let Some(key) = contributors_key {
+                set_credits(tag, key, contributors, "contributor");
+            }
+        }
+    }
+}
+
+/// Writes the release, original release and cover art dates into the fields each format
+/// expects, and their years. lofty only writes ID3v2.4, so ID3v2 tags get its timestamp frames,
+/// with ID3v2.3's year frames alongside for players that only read those.
+pub fn write_dates(
+    tag: &mut Tag,
+    profile: &Profile,
+    release: NaiveDate,
+    original: Option<NaiveDate>,
+    cover_art: NaiveDate,
+) {
+    let date = |x: NaiveDate| x.format("%F").to_string();
+    let year = |x: NaiveDate| x.format("%Y").to_string();
+
+    // TDRC and TDOR for ID3v2, DATE and ORIGINALDATE for Vorbis, ©day and an ORIGINALDATE
+    // freeform atom for MP4, and Year and ORIGINALDATE for APE
+    set_field(tag, profile, Field::ReleaseDate, date(release));
+    if let Some(original) = original {
+        set_field(tag, profile, Field::OriginalReleaseDate, date(original));
+    }
+
+    // Along with the fields some players read instead, and the years. MP4 players take the year
+    // from ©day, and APE's Year already has the whole date.
+    let (release_year, original_year) = match tag.tag_type() {
+        TagType::Id3v2 => {
+            // lofty's name for TDRL
+            tag.insert_text(ItemKey::PodcastReleaseDate, date(release));
+            (
+                Some(ItemKey::Unknown("TYER".to_string())),
+                Some(ItemKey::Unknown("TORY".to_string())),
+            )
+        }
+        TagType::VorbisComments => (Some(ItemKey::Year), Some(custom_key(tag, "ORIGINALYEAR"))),
+        TagType::Mp4Ilst => (None, Some(custom_key(tag, "ORIGINALYEAR"))),
+        TagType::Ape => (None, None),
+        _ => (Some(ItemKey::Year), None),
+    };
+    if let Some(key) = release_year {
+        set_texts(tag, key, Some(year(release)));
+    }
+    if let (Some(key), Some(original)) = (original_year, original) {
+        set_texts(tag, key, Some(year(original)));
+    }
+
+    set_field(tag, profile, Field::CoverArtDate, date(cover_art));
+}
+
+/// Records which hsmusic track a file was matched to, so later runs can skip matching. By
+/// default, the Bandcamp URL goes in WOAF for ID3v2, and a custom field everywhere else.
+pub fn write_ids(tag: &mut Tag, profile: &Profile, album: &Album, track: &Track) {
+    set_field(
+        tag,
+        profile,
+        Field::HsmusicAlbum,
+        album.directory.to_string(),
+    );
+    set_field(
+        tag,
+        profile,
+        Field::HsmusicTrack,
+        track.directory.to_string(),
+    );
+
+    if let Some(url) = track.urls.iter().find(|x| x.contains("bandcamp.com")) {
+        set_field(tag, profile, Field::BandcampUrl, url.to_string());
+    }
+}
+
+/// The hsmusic album and track directories written by [`write_ids`], if any.
+pub fn read_ids(tag: &Tag, profile: &Profile) -> Option<(String, String)> {
+    let album = tag.get_string(&profile.key(tag, Field::HsmusicAlbum)?)?;
+    let track = tag.get_string(&profile.key(tag, Field::HsmusicTrack)?)?;
+    Some((album.to_string(), track.to_string()))
+}
+
+/// Whether `url` is on one of `hosts` or their subdomains. No hosts means anything goes.
+pub fn matches_host(url: &str, hosts: &[String]) -> bool {
+    if hosts.is_empty() {
+        return true;
+    }
+
+    let host = match Url::parse(url)
+        .ok()
+        .and_then(|x| x.host_str().map(String::from))
+    {
+        Some(host) => host,
+        None => return false,
+    };
+
+    hosts
+        .iter()
+        .any(|x| host == *x || host.ends_with(&format!(".{}", x)))
+}
+
+fn filter_hosts<'a>(urls: &[&'a str], hosts: &[String]) -> Vec<&'a str> {
+    urls.iter()
+        .copied()
+        .filter(|x| matches_host(x, hosts))
+        .collect()
+}
+
+/// Writes links to the track's releases and its artist: by default, the first track link as
+/// WOAS for ID3v2 or WEBSITE elsewhere, the rest of the track's and album's links as URL, and
+/// the artist's first link as WOAR or ARTIST_URL. The rest of the links go in a custom text
+/// field, a TXXX frame for ID3v2, because ID3v2 only allows one WXXX frame per description.
+pub fn write_urls(
+    tag: &mut Tag,
+    profile: &Profile,
+    track_urls: &[&str],
+    album_urls: &[&str],
+    artist_urls: &[&str],
+    hosts: &[String],
+) {
+    let track_urls = filter_hosts(track_urls, hosts);
+    let mut release_urls: Vec<_> = track_urls
+        .iter()
+        .chain(&filter_hosts(album_urls, hosts))
+        .map(|x| x.to_string())
+        .collect();
+
+    let track_url = match profile.key(tag, Field::TrackUrl) {
+        Some(_) if !track_urls.is_empty() => vec![release_urls.remove(0)],
+        _ => vec![],
+    };
+    set_multi_field(tag, profile, Field::TrackUrl, track_url);
+    set_multi_field(tag, profile, Field::ReleaseUrls, release_urls);
+
+    let artist_url = filter_hosts(artist_urls, hosts)
+        .first()
+        .map(|x| x.to_string());
+    set_multi_field(
+        tag,
+        profile,
+        Field::ArtistUrl,
+        artist_url.into_iter().collect(),
+    );
+}
+
+/// Writes the names of the tracks `track` references, and of the tracks that reference it.
+pub fn write_references(
+    tag: &mut Tag,
+    profile: &Profile,
+    references: &[&Track],
+    referenced_by: &[&Track],
+) {
+    let names = |tracks: &[&Track]| tracks.iter().map(|x| x.name.clone()).collect();
+    set_multi_field(tag, profile, Field::References, names(references));
+    set_multi_field(tag, profile, Field::ReferencedBy, names(referenced_by));
+}
+
+/// The frame and description of an ID3v2 `TXXX:<description>` or `WXXX:<description>` key.
+fn described_frame(key: &ItemKey) -> Option<(&str, &str)> {
+    match key {
+        ItemKey::Unknown(key) => key
+            .split_once(':')
+            .filter(|(id, _)| *id == "TXXX" || *id == "WXXX"),
+        _ => None,
+    }
+}
+
+/// An ID3v2 tag as a [`Tag`], keeping TXXX and WXXX descriptions in their keys, which lofty's own
+/// conversion drops.
+fn from_id3v2(id3v2: &Id3v2Tag) -> Tag {
+    let mut tag = Tag::from(id3v2.clone());
+    tag.retain_items(|x| !matches!(x.key(), ItemKey::Unknown(id) if id == "TXXX" || id == "WXXX"));
+
+    for frame in id3v2.iter() {
+        let (key, value) = match frame.content() {
+            FrameValue::UserText(x) => (
+                format!("TXXX:{}", x.description),
+                ItemValue::Text(x.content.clone()),
+            ),
+            FrameValue::UserURL(x) => (
+                format!("WXXX:{}", x.description),
+                ItemValue::Locator(x.content.clone()),
+            ),
+            _ => continue,
+        };
+        tag.push_item_unchecked(TagItem::new(ItemKey::Unknown(key), value));
+    }
+
+    tag
+}
+
+/// The ID3v2 tag to write for `tag`, with a described TXXX or WXXX frame for each custom key.
+fn to_id3v2(tag: &Tag) -> Id3v2Tag {
+    let mut plain = tag.clone();
+    plain.retain_items(|x| described_frame(x.key()).is_none());
+    let mut id3v2 = Id3v2Tag::from(plain);
+
+    for item in tag.items() {
+        let (id, description) = match described_frame(item.key()) {
+            Some(x) => x,
+            None => continue,
+        };
+        let frame = EncodedTextFrame {
+            encoding: TextEncoding::UTF8,
+            description: description.to_string(),
+            content: match item.value() {
+                ItemValue::Text(x) | ItemValue::Locator(x) => x.clone(),
+                ItemValue::Binary(_) => continue,
+            },
+        };
+        let value = match id {
+            "TXXX" => FrameValue::UserText(frame),
+            _ => FrameValue::UserURL(frame),
+        };
+        if let Ok(frame) = Frame::new(id, value, FrameFlags::default()) {
+            id3v2.insert(frame);
+        }
+    }
+
+    id3v2
+}
+
+/// Reads a file's tags, with ID3v2 custom fields keyed the way [`custom_key`] expects.
+pub fn read_from_path(path: &Path) -> lofty::Result<TaggedFile> {
+    let mut metadata = lofty::read_from_path(path, true)?;
+    let file_type = metadata.file_type();
+    if let Some(tag) = metadata.tag_mut(&TagType::Id3v2) {
+        let mut file = File::open(path)?;
+        let id3v2 = match file_type {
+            FileType::MP3 => Mp3File::read_from(&mut file, false)?.id3v2_tag().cloned(),
+            FileType::WAV => WavFile::read_from(&mut file, false)?.id3v2_tag().cloned(),
+            FileType::AIFF => AiffFile::read_from(&mut file, false)?.id3v2_tag().cloned(),
+            _ => None,
+        };
+        if let Some(id3v2) = id3v2 {
+            *tag = from_id3v2(&id3v2);
+        }
+    }
+    Ok(metadata)
+}
+
+/// Writes every tag of a file read with [`read_from_path`], giving ID3v2 custom fields their
+/// descriptions back.
+pub fn save_to_path(metadata: &TaggedFile, path: &Path) -> lofty::Result<()> {
+    for tag in metadata.tags() {
+        let file_type = metadata.file_type();
+        if tag.tag_type() == TagType::Id3v2
+            && matches!(file_type, FileType::MP3 | FileType::WAV | FileType::AIFF)
+        {
+            to_id3v2(tag).save_to_path(path)?;
+        } else {
+            tag.save_to_path(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lofty::mp4::Ilst;
+    use lofty::ogg::VorbisComments;
+    use std::borrow::Cow;
+
+    fn track(name: &str, directory: &'static str) -> Track<'static> {
+        Track {
+            name: name.to_string(),
+            commentary: None,
+            lyrics: None,
+            original_date: None,
+            cover_art_date: "2009-01-01".parse().unwrap(),
+            references: vec![],
+            artists: None,
+            cover_artists: None,
+            art_tags: vec![],
+            contributors: vec![],
+            directory: Cow::Borrowed(directory),
+            aka: None,
+            duration: 0,
+            urls: vec![],
+            group: "",
+            color: "",
+            track_num: 1,
+        }
+    }
+
+    fn album(tracks: Vec<Track<'static>>) -> Album<'static> {
+        let date = "2009-01-01".parse().unwrap();
+        Album {
+            name: "Homestuck Vol. 5",
+            artists: None,
+            date,
+            track_art_date: date,
+            cover_art_date: date,
+            cover_artists: None,
+            has_track_art: false,
+            track_cover_artists: None,
+            art_tags: vec![],
+            commentary: None,
+            urls: vec![],
+            groups: vec![],
+            directory: Cow::Borrowed("homestuck-vol-5"),
+            is_major_release: true,
+            color: "",
+            uses_groups: false,
+            tracks,
+        }
+    }
+
+    const TAG_TYPES: [TagType; 4] = [
+        TagType::Id3v2,
+        TagType::Ape,
+        TagType::VorbisComments,
+        TagType::Mp4Ilst,
+    ];
+
+    /// `tag` after writing it to an MP3 and reading it back.
+    fn saved_mp3(tag: Tag) -> Tag {
+        // A few silent MPEG-1 layer III frames
+        let frame = [&[0xff, 0xfb, 0x90, 0x00][..], &[0; 413]].concat();
+        let path = std::env::temp_dir().join(format!(
+            "hsmusicifier-{}-{:?}.mp3",
+            std::process::id(),
+            tag.tag_type()
+        ));
+        std::fs::write(&path, frame.repeat(3)).unwrap();
+
+        let tag_type = tag.tag_type();
+        let mut metadata = read_from_path(&path).unwrap();
+        metadata.insert_tag(tag);
+        save_to_path(&metadata, &path).unwrap();
+
+        let mut metadata = read_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        metadata.remove_tag(tag_type).unwrap()
+    }
+
+    /// `tag` the way it reads back after lofty writes it. ID3v2 and APE go through an MP3, and
+    /// the rest through the format's own tag, which lofty converts the same way when writing.
+    fn saved(tag: Tag) -> Tag {
+        match tag.tag_type() {
+            TagType::Id3v2 | TagType::Ape => saved_mp3(tag),
+            TagType::VorbisComments => VorbisComments::from(tag).into(),
+            TagType::Mp4Ilst => Ilst::from(tag).into(),
+            tag_type => panic!("{:?} isn't tested", tag_type),
+        }
+    }
+
+    /// Every value of `field`, with null-separated values split apart.
+    fn values(tag: &Tag, profile: &Profile, field: Field) -> Vec<String> {
+        tag.get_items(&profile.key(tag, field).unwrap())
+            .flat_map(|x| match x.value() {
+                ItemValue::Text(x) | ItemValue::Locator(x) => x.split('\0').map(String::from),
+                ItemValue::Binary(_) => panic!("{:?} isn't text", x.key()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ids_survive_saving() {
+        let album = album(vec![track(
+            "Showtime (Imp Strife Mix)",
+            "showtime-imp-strife-mix",
+        )]);
+        let profile = Profile::default();
+
+        for tag_type in TAG_TYPES {
+            let mut tag = Tag::new(tag_type);
+            write_ids(&mut tag, &profile, &album, &album.tracks[0]);
+            assert_eq!(
+                read_ids(&saved(tag), &profile),
+                Some((
+                    "homestuck-vol-5".to_string(),
+                    "showtime-imp-strife-mix".to_string()
+                )),
+                "{:?}",
+                tag_type
+            );
+        }
+    }
+
+    #[test]
+    fn custom_fields_are_described_txxx_frames() {
+        let mut tag = Tag::new(TagType::Id3v2);
+        let key = custom_key(&tag, "HSMUSIC_ALBUM");
+        set_texts(&mut tag, key, Some("homestuck-vol-5".to_string()));
+
+        let id3v2 = to_id3v2(&tag);
+        let frame = id3v2.get("TXXX").unwrap();
+        match frame.content() {
+            FrameValue::UserText(x) => {
+                assert_eq!(x.description, "HSMUSIC_ALBUM");
+                assert_eq!(x.content, "homestuck-vol-5");
+            }
+            value => panic!("{:?} isn't a TXXX frame", value),
+        }
+    }
+
+    #[test]
+    fn foobar2000_grouping_survives_saving() {
+        let profile = Profile::builtin("foobar2000").unwrap();
+
+        for tag_type in TAG_TYPES {
+            let mut tag = Tag::new(tag_type);
+            set_multi_field(
+                &mut tag,
+                &profile,
+                Field::Grouping,
+                vec!["Homestuck".to_string()],
+            );
+            if tag_type == TagType::Id3v2 {
+                assert_eq!(
+                    profile.key(&tag, Field::Grouping),
+                    Some(ItemKey::Unknown("TXXX:GROUPING".to_string()))
+                );
+            }
+
+            let tag = saved(tag);
+            assert_eq!(
+                values(&tag, &profile, Field::Grouping),
+                ["Homestuck"],
+                "{:?}",
+                tag_type
+            );
+        }
+    }
+
+    #[test]
+    fn urls_survive_saving() {
+        let profile = Profile::default();
+
+        for tag_type in TAG_TYPES {
+            let mut tag = Tag::new(tag_type);
+            write_urls(
+                &mut tag,
+                &profile,
+                &[
+                    "https://homestuck.bandcamp.com/track/showtime-imp-strife-mix",
+                    "https://www.youtube.com/watch?v=7IHdb0Y7tV4",
+                ],
+                &["https://homestuck.bandcamp.com/album/homestuck-vol-5"],
+                &["https://tumblr.com/example"],
+                &[],
+            );
+
+            let tag = saved(tag);
+            assert_eq!(
+                values(&tag, &profile, Field::TrackUrl),
+                ["https://homestuck.bandcamp.com/track/showtime-imp-strife-mix"],
+                "{:?}",
+                tag_type
+            );
+            assert_eq!(
+                values(&tag, &profile, Field::ReleaseUrls),
+                [
+                    "https://www.youtube.com/watch?v=7IHdb0Y7tV4",
+                    "https://homestuck.bandcamp.com/album/homestuck-vol-5",
+                ],
+                "{:?}",
+                tag_type
+            );
+            assert_eq!(
+                values(&tag, &profile, Field::ArtistUrl),
+                ["https://tumblr.com/example"],
+                "{:?}",
+                tag_type
+            );
+        }
+    }
+
+    #[test]
+    fn color_survives_saving() {
+        let profile = Profile::default();
+
+        for tag_type in TAG_TYPES {
+            let mut tag = Tag::new(tag_type);
+            set_field(&mut tag, &profile, Field::Color, "#ff6000".to_string());
+            assert_eq!(
+                values(&saved(tag), &profile, Field::Color),
+                ["#ff6000"],
+                "{:?}",
+                tag_type
+            );
+        }
+    }
+
+    #[test]
+    fn references_survive_saving() {
+        let profile = Profile::default();
+        let references = [track("Sburban Jungle", "sburban-jungle")];
+        let referenced_by = [
+            track("Showtime (Imp Strife Mix)", "showtime-imp-strife-mix"),
+            track("Harlequin", "harlequin"),
+        ];
+
+        for tag_type in TAG_TYPES {
+            let mut tag = Tag::new(tag_type);
+            write_references(
+                &mut tag,
+                &profile,
+                &references.iter().collect::<Vec<_>>(),
+                &referenced_by.iter().collect::<Vec<_>>(),
+            );
+
+            let tag = saved(tag);
+            assert_eq!(
+                values(&tag, &profile, Field::References),
+                ["Sburban Jungle"],
+                "{:?}",
+                tag_type
+            );
+            assert_eq!(
+                values(&tag, &profile, Field::ReferencedBy),
+                ["Showtime (Imp Strife Mix)", "Harlequin"],
+                "{:?}",
+                tag_type
+            );
+        }
+    }
+
+    #[test]
+    fn commentary_survives_saving() {
+        let profile = Profile::default();
+        let commentary = "Toby Fox:\nThis one's a remix.\n\nJit:\nAnd a good one.";
+
+        for tag_type in TAG_TYPES {
+            let mut tag = Tag::new(tag_type);
+            set_field(
+                &mut tag,
+                &profile,
+                Field::Commentary,
+                commentary.to_string(),
+            );
+            assert_eq!(
+                values(&saved(tag), &profile, Field::Commentary),
+                [commentary],
+                "{:?}",
+                tag_type
+            );
+        }
+    }
+
+    #[test]
+    fn dates_survive_saving() {
+        let profile = Profile::default();
+        let date = |x: &str| x.parse().unwrap();
+        let unknown = |x: &str| ItemKey::Unknown(x.to_string());
+
+        for tag_type in TAG_TYPES {
+            let mut tag = Tag::new(tag_type);
+            write_dates(
+                &mut tag,
+                &profile,
+                date("2009-04-13"),
+                Some(date("2008-06-12")),
+                date("2009-04-10"),
+            );
+
+            let tag = saved(tag);
+            let fields = match tag_type {
+                TagType::Id3v2 => vec![
+                    (ItemKey::RecordingDate, "2009-04-13"),
+                    (ItemKey::OriginalReleaseDate, "2008-06-12"),
+                    (ItemKey::PodcastReleaseDate, "2009-04-13"),
+                    (unknown("TYER"), "2009"),
+                    (unknown("TORY"), "2008"),
+                ],
+                TagType::Ape => vec![
+                    (ItemKey::Year, "2009-04-13"),
+                    (unknown("ORIGINALDATE"), "2008-06-12"),
+                ],
+                TagType::VorbisComments => vec![
+                    (ItemKey::RecordingDate, "2009-04-13"),
+                    (ItemKey::OriginalReleaseDate, "2008-06-12"),
+                    (ItemKey::Year, "2009"),
+                    (unknown("ORIGINALYEAR"), "2008"),
+                ],
+                _ => vec![
+                    (ItemKey::RecordingDate, "2009-04-13"),
+                    (unknown("----:com.apple.iTunes:ORIGINALDATE"), "2008-06-12"),
+                    (unknown("----:com.apple.iTunes:ORIGINALYEAR"), "2008"),
+                ],
+            };
+            for (key, value) in fields {
+                assert_eq!(
+                    tag.get_string(&key),
+                    Some(value),
+                    "{:?} {:?}",
+                    tag_type,
+                    key
+                );
+            }
+            assert_eq!(
+                values(&tag, &profile, Field::CoverArtDate),
+                ["2009-04-10"],
+                "{:?}",
+                tag_type
+            );
+        }
+    }
+
+    #[test]
+    fn credits_survive_saving() {
+        let profile = Profile::default();
+        let mut track = track("Showtime (Imp Strife Mix)", "showtime-imp-strife-mix");
+        track.artists = Some(vec![
+            Contributor {
+                who: "Toby Fox",
+                what: None,
+            },
+            Contributor {
+                who: "Jit",
+                what: Some("vocals"),
+            },
+        ]);
+        track.contributors = vec![Contributor {
+            who: "Cookiefonster",
+            what: Some("mixing"),
+        }];
+
+        for tag_type in TAG_TYPES {
+            let mut tag = Tag::new(tag_type);
+            write_credits(&mut tag, &profile, &track);
+
+            if tag_type == TagType::Id3v2 {
+                // lofty stops reading text frames at the first NUL, so check
+                // the frames as written instead of reading them back
+                let id3v2 = to_id3v2(&tag);
+                for (id, expected) in [("TMCL", "vocals\0Jit"), ("TIPL", "mixing\0Cookiefonster")] {
+                    match id3v2.get(id).map(Frame::content) {
+                        Some(FrameValue::Text { value, .. }) => assert_eq!(value, expected),
+                        value => panic!("{:?} isn't a {} frame", value, id),
+                    }
+                }
+            } else {
+                assert_eq!(
+                    values(&saved(tag), &profile, Field::Performers),
+                    ["Jit (vocals)", "Cookiefonster (mixing)"],
+                    "{:?}",
+                    tag_type
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! path templates, for getting album, track number and title out of untagged files' paths
+use anyhow::{anyhow, ensure, Error, Result};
+use regex::Regex;
+use std::path::Path;
+use std::str::FromStr;
+
+/// What a path template pulled out of a path.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathInfo {
+    pub album: Option<String>,
+    pub artist: Option<String>,
+    pub disc: Option<usize>,
+    pub track_num: Option<usize>,
+    pub title: Option<String>,
+}
+
+/// A template like `{album}/{track:02} {title}`, matched against the end of a file's path
+/// relative to the input directory, without its extension. Placeholders are `{album}`,
+/// `{artist}`, `{title}`, `{disc}` and `{track}`, and `{_}` matches anything. A width like
+/// `{track:02}` is allowed, but numbers match regardless of padding.
+#[derive(Clone, Debug)]
+pub struct PathTemplate {
+    template: String,
+    regex: Regex,
+}
+
+impl PartialEq for PathTemplate {
+    fn eq(&self, other: &Self) -> bool {
+        self.template == other.template
+    }
+}
+
+impl Eq for PathTemplate {}
+
+impl FromStr for PathTemplate {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut pattern = "(?:^|/)".to_string();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            pattern.push_str(&regex::escape(&rest[..start]));
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("Unclosed {{ in path template {}!", template))?;
+            let placeholder = &rest[start + 1..start + end];
+            let (name, _width) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+
+            let group = match name {
+                "album" | "artist" | "title" => format!("(?P<{}>[^/]+?)", name),
+                "disc" | "track" => format!(r"(?P<{}>\d+)", name),
+                "_" => "[^/]*?".to_string(),
+                _ => return Err(anyhow!("Bad placeholder {{{}}} in path template!", name)),
+            };
+            ensure!(
+                name == "_" || !pattern.contains(&format!("(?P<{}>", name)),
+                "{{{}}} is in path template {} twice!",
+                name,
+                template
+            );
+            pattern.push_str(&group);
+
+            rest = &rest[start + end + 1..];
+        }
+
+        pattern.push_str(&regex::escape(rest));
+        pattern.push('$');
+
+        Ok(Self {
+            template: template.to_string(),
+            regex: Regex::new(&pattern)?,
+        })
+    }
+}
+
+impl PathTemplate {
+    /// Matches `rel_path` against the template, if it fits.
+    pub fn parse(&self, rel_path: &Path) -> Option<PathInfo> {
+        let path = rel_path.with_extension("");
+        let path = path
+            .iter()
+            .map(|x| x.to_str())
+            .collect::<Option<Vec<_>>>()?
+            .join("/");
+
+        let captures = self.regex.captures(&path)?;
+        let text = |name| captures.name(name).map(|x| x.as_str().trim().to_string());
+        let number = |name| captures.name(name).and_then(|x| x.as_str().parse().ok());
+
+        Some(PathInfo {
+            album: text("album"),
+            artist: text("artist"),
+            disc: number("disc"),
+            track_num: number("track"),
+            title: text("title"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(template: &str, rel_path: &str) -> Option<PathInfo> {
+        template
+            .parse::<PathTemplate>()
+            .unwrap()
+            .parse(Path::new(rel_path))
+    }
+
+    #[test]
+    fn parses_album_track_and_title() {
+        assert_eq!(
+            parse(
+                "{album}/{track} {title}",
+                "Homestuck Vol. 5/01 Showtime.mp3"
+            ),
+            Some(PathInfo {
+                album: Some("Homestuck Vol. 5".to_string()),
+                track_num: Some(1),
+                title: Some("Showtime".to_string()),
+                ..PathInfo::default()
+            })
+        );
+    }
+
+    #[test]
+    fn numbers_match_regardless_of_padding() {
+        for rel_path in ["1-2 Showtime.flac", "01-002 Showtime.flac"] {
+            let info = parse("{disc:02}-{track:03} {title}", rel_path).unwrap();
+            assert_eq!(
+                (info.disc, info.track_num),
+                (Some(1), Some(2)),
+                "{}",
+                rel_path
+            );
+        }
+    }
+
+    #[test]
+    fn underscores_match_anything() {
+        let info = parse(
+            "{_}/{album}/{track} - {_} - {title}",
+            "Music/Homestuck/Vol. 5/03 - Toby Fox - Harlequin.mp3",
+        )
+        .unwrap();
+        assert_eq!(info.album.as_deref(), Some("Vol. 5"));
+        assert_eq!(info.track_num, Some(3));
+        assert_eq!(info.title.as_deref(), Some("Harlequin"));
+    }
+
+    #[test]
+    fn matches_the_end_of_the_path() {
+        let info = parse("{title}", "Homestuck Vol. 5/Showtime.mp3").unwrap();
+        assert_eq!(info.title.as_deref(), Some("Showtime"));
+        assert_eq!(parse("{album}/{track} {title}", "Showtime.mp3"), None);
+    }
+
+    #[test]
+    fn keeps_dots_in_paths() {
+        let info = parse(
+            "{album}/{track}. {title}",
+            "Homestuck Vol. 5/12. Mr. Sandman.opus",
+        )
+        .unwrap();
+        assert_eq!(info.album.as_deref(), Some("Homestuck Vol. 5"));
+        assert_eq!(info.track_num, Some(12));
+        assert_eq!(info.title.as_deref(), Some("Mr. Sandman"));
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        for template in [
+            "{album}/{track} {title} {album}",
+            "{title}/{_}/{_}/{title}",
+            "{album}/{name}",
+            "{album}/{track",
+        ] {
+            assert!(template.parse::<PathTemplate>().is_err(), "{}", template);
+        }
+        assert!("{_}/{_}/{title}".parse::<PathTemplate>().is_ok());
+    }
+}